@@ -0,0 +1,56 @@
+use std::{env, fs, path::Path};
+
+/// Generates the opcode tables from `instructions.json` so the emulator never
+/// has to read or string-match it at runtime. Every mnemonic becomes an
+/// `OpKind` variant, so a mnemonic without a matching variant fails to compile.
+fn main() {
+    println!("cargo:rerun-if-changed=instructions.json");
+
+    let json_string = fs::read_to_string("instructions.json").expect("File not found");
+    let json: serde_json::Value = serde_json::from_str(json_string.as_str()).expect("Invalid JSON");
+
+    let mut out = String::new();
+    for (table, name) in [
+        ("instructions", "INSTRUCTION_DATA"),
+        ("cb_instructions", "CB_INSTRUCTION_DATA"),
+    ] {
+        let entries = json
+            .get(table)
+            .and_then(|t| t.as_array())
+            .unwrap_or_else(|| panic!("Missing table {table}"));
+        assert_eq!(entries.len(), 256, "Table {table} must have 256 entries");
+
+        out += &format!("pub static {name}: [InstructionData; 256] = [\n");
+        for entry in entries {
+            let mnemonic = entry["mnemonic"].as_str().expect("Invalid mnemonic");
+            let bytes = entry["bytes"].as_u64().expect("Invalid bytes");
+            let cycles: Vec<String> = entry["cycles"]
+                .as_array()
+                .expect("Invalid cycles")
+                .iter()
+                .map(|c| c.to_string())
+                .collect();
+
+            out += &format!(
+                "    InstructionData {{ kind: OpKind::{}, bytes: {}, cycles: &[{}] }},\n",
+                kind_name(mnemonic),
+                bytes,
+                cycles.join(", ")
+            );
+        }
+        out += "];\n\n";
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("opcodes.rs"), out).unwrap();
+}
+
+/// `LDH` -> `Ldh`, with all the unused `ILLEGAL_XX` slots sharing one kind
+fn kind_name(mnemonic: &str) -> String {
+    if mnemonic.starts_with("ILLEGAL") {
+        return String::from("Illegal");
+    }
+
+    let (first, rest) = mnemonic.split_at(1);
+    first.to_string() + &rest.to_lowercase()
+}
@@ -0,0 +1,345 @@
+use core::fmt;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs, io,
+    path::Path,
+};
+
+use sdl2::{
+    controller::{Axis, Button as ControllerButton},
+    keyboard::Keycode,
+};
+use serde::Deserialize;
+
+use crate::emulator::Button;
+
+/// Stick travel, out of 32767, ignored before it counts as a D-pad press
+const DEFAULT_DEADZONE: i16 = 8000;
+
+/// What a key or controller input does: a Game Boy button or a frontend
+/// hotkey
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Action {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+    /// Toggles emulation on and off
+    Pause,
+    /// Runs unthrottled while held
+    FastForward,
+    /// Writes battery-backed RAM out now rather than on the next autosave
+    Save,
+}
+
+impl Action {
+    pub fn button(self) -> Option<Button> {
+        match self {
+            Action::Right => Some(Button::Right),
+            Action::Left => Some(Button::Left),
+            Action::Up => Some(Button::Up),
+            Action::Down => Some(Button::Down),
+            Action::A => Some(Button::A),
+            Action::B => Some(Button::B),
+            Action::Select => Some(Button::Select),
+            Action::Start => Some(Button::Start),
+            Action::Pause | Action::FastForward | Action::Save => None,
+        }
+    }
+}
+
+/// Bindings file as written, inputs by their SDL names. Axes are a name
+/// followed by `+` or `-` for the direction. Sections left out keep their
+/// defaults.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BindingsConfig {
+    keyboard: BTreeMap<String, Action>,
+    controller: BTreeMap<String, Action>,
+    axes: BTreeMap<String, Action>,
+    deadzone: i16,
+}
+
+impl Default for BindingsConfig {
+    fn default() -> Self {
+        let keyboard = [
+            ("Right", Action::Right),
+            ("Left", Action::Left),
+            ("Up", Action::Up),
+            ("Down", Action::Down),
+            ("X", Action::A),
+            ("Z", Action::B),
+            ("Backspace", Action::Select),
+            ("Return", Action::Start),
+            ("P", Action::Pause),
+            ("Tab", Action::FastForward),
+            ("F5", Action::Save),
+        ];
+        let controller = [
+            ("dpright", Action::Right),
+            ("dpleft", Action::Left),
+            ("dpup", Action::Up),
+            ("dpdown", Action::Down),
+            ("a", Action::A),
+            ("b", Action::B),
+            ("back", Action::Select),
+            ("start", Action::Start),
+            ("guide", Action::Pause),
+            ("rightshoulder", Action::FastForward),
+        ];
+        let axes = [
+            ("leftx+", Action::Right),
+            ("leftx-", Action::Left),
+            ("lefty-", Action::Up),
+            ("lefty+", Action::Down),
+        ];
+        let names = |bindings: &[(&str, Action)]| {
+            bindings
+                .iter()
+                .map(|&(name, action)| (name.to_string(), action))
+                .collect()
+        };
+
+        Self {
+            keyboard: names(&keyboard),
+            controller: names(&controller),
+            axes: names(&axes),
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnknownKey(String),
+    UnknownControllerButton(String),
+    /// Not an SDL axis name followed by `+` or `-`
+    UnknownAxis(String),
+    /// Outside 0-32767
+    InvalidDeadzone(i16),
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Couldn't read bindings: {}", e),
+            Self::Json(e) => write!(f, "Couldn't parse bindings: {}", e),
+            Self::UnknownKey(name) => write!(f, "Unknown key {:?} in bindings", name),
+            Self::UnknownControllerButton(name) => {
+                write!(f, "Unknown controller button {:?} in bindings", name)
+            }
+            Self::UnknownAxis(name) => write!(
+                f,
+                "Unknown axis {:?} in bindings, expected e.g. \"leftx+\"",
+                name
+            ),
+            Self::InvalidDeadzone(deadzone) => write!(
+                f,
+                "Invalid deadzone {} in bindings, expected 0 to 32767",
+                deadzone
+            ),
+        }
+    }
+}
+
+impl Error for BindingsError {}
+
+impl From<io::Error> for BindingsError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for BindingsError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+/// Keyboard and game controller inputs mapped to actions
+#[derive(Debug)]
+pub struct Bindings {
+    keys: HashMap<Keycode, Action>,
+    buttons: HashMap<ControllerButton, Action>,
+    /// Keyed by axis and whether it's the positive direction
+    axes: HashMap<(Axis, bool), Action>,
+    deadzone: i16,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::try_from(BindingsConfig::default()).expect("The default bindings use valid names")
+    }
+}
+
+impl TryFrom<BindingsConfig> for Bindings {
+    type Error = BindingsError;
+
+    fn try_from(config: BindingsConfig) -> Result<Self, BindingsError> {
+        let deadzone = check_deadzone(config.deadzone)?;
+        let keys = config
+            .keyboard
+            .into_iter()
+            .map(|(name, action)| match Keycode::from_name(&name) {
+                Some(keycode) => Ok((keycode, action)),
+                None => Err(BindingsError::UnknownKey(name)),
+            })
+            .collect::<Result<_, _>>()?;
+        let buttons = config
+            .controller
+            .into_iter()
+            .map(
+                |(name, action)| match ControllerButton::from_string(&name) {
+                    Some(button) => Ok((button, action)),
+                    None => Err(BindingsError::UnknownControllerButton(name)),
+                },
+            )
+            .collect::<Result<_, _>>()?;
+        let axes = config
+            .axes
+            .into_iter()
+            .map(|(name, action)| {
+                let axis = split_axis(&name)
+                    .and_then(|(axis, positive)| Some((Axis::from_string(axis)?, positive)));
+                match axis {
+                    Some(axis) => Ok((axis, action)),
+                    None => Err(BindingsError::UnknownAxis(name)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            keys,
+            buttons,
+            axes,
+            deadzone,
+        })
+    }
+}
+
+impl Bindings {
+    /// Reads bindings from the JSON file at `path`, or the defaults if
+    /// there's no such file
+    pub fn load(path: &Path) -> Result<Self, BindingsError> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let config: BindingsConfig = serde_json::from_str(&json)?;
+        Self::try_from(config)
+    }
+
+    pub fn key(&self, keycode: Keycode) -> Option<Action> {
+        self.keys.get(&keycode).copied()
+    }
+
+    pub fn controller_button(&self, button: ControllerButton) -> Option<Action> {
+        self.buttons.get(&button).copied()
+    }
+
+    /// Directions of `axis` with an action bound, each with the action and
+    /// whether the stick at `value` holds it
+    pub fn axis(&self, axis: Axis, value: i16) -> Vec<(bool, Action, bool)> {
+        [false, true]
+            .into_iter()
+            .filter_map(|positive| {
+                let action = self.axes.get(&(axis, positive))?;
+                Some((positive, *action, axis_held(value, positive, self.deadzone)))
+            })
+            .collect()
+    }
+}
+
+/// Splits `leftx+` into the axis name and whether it's the positive
+/// direction
+fn split_axis(name: &str) -> Option<(&str, bool)> {
+    if let Some(axis) = name.strip_suffix('+') {
+        Some((axis, true))
+    } else {
+        name.strip_suffix('-').map(|axis| (axis, false))
+    }
+}
+
+/// Stick positions run from -32768 to 32767, so only 0-32767 makes sense
+fn check_deadzone(deadzone: i16) -> Result<i16, BindingsError> {
+    if deadzone < 0 {
+        return Err(BindingsError::InvalidDeadzone(deadzone));
+    }
+    Ok(deadzone)
+}
+
+/// Whether a stick at `value` is pushed past the deadzone in the given
+/// direction
+fn axis_held(value: i16, positive: bool, deadzone: i16) -> bool {
+    if positive {
+        value > deadzone
+    } else {
+        value < -deadzone
+    }
+}
+
+#[cfg(test)]
+mod bindings_tests {
+    use super::{
+        axis_held, check_deadzone, split_axis, Action, BindingsConfig, BindingsError,
+        DEFAULT_DEADZONE,
+    };
+
+    #[test]
+    fn test_missing_sections_keep_defaults() {
+        let config: BindingsConfig =
+            serde_json::from_str(r#"{ "keyboard": { "A": "A", "Space": "Pause" } }"#).unwrap();
+
+        assert_eq!(config.keyboard.len(), 2);
+        assert_eq!(config.keyboard["Space"], Action::Pause);
+        assert_eq!(config.controller["start"], Action::Start);
+        assert_eq!(config.axes["lefty-"], Action::Up);
+        assert_eq!(config.deadzone, DEFAULT_DEADZONE);
+    }
+
+    #[test]
+    fn test_unknown_action_is_rejected() {
+        let config = serde_json::from_str::<BindingsConfig>(r#"{ "keyboard": { "A": "Jump" } }"#);
+        assert!(config.is_err());
+
+        let config = serde_json::from_str::<BindingsConfig>(r#"{ "keybaord": {} }"#);
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_split_axis() {
+        assert_eq!(split_axis("leftx+"), Some(("leftx", true)));
+        assert_eq!(split_axis("righty-"), Some(("righty", false)));
+        assert_eq!(split_axis("leftx"), None);
+    }
+
+    #[test]
+    fn test_negative_deadzone_is_rejected() {
+        let config: BindingsConfig = serde_json::from_str(r#"{ "deadzone": -1 }"#).unwrap();
+        assert!(matches!(
+            check_deadzone(config.deadzone),
+            Err(BindingsError::InvalidDeadzone(-1))
+        ));
+        assert!(matches!(check_deadzone(32767), Ok(32767)));
+
+        // past i16 doesn't parse at all
+        assert!(serde_json::from_str::<BindingsConfig>(r#"{ "deadzone": 40000 }"#).is_err());
+    }
+
+    #[test]
+    fn test_deadzone() {
+        assert!(!axis_held(8000, true, 8000));
+        assert!(axis_held(8001, true, 8000));
+        assert!(!axis_held(8001, false, 8000));
+        assert!(axis_held(-32768, false, 8000));
+        assert!(!axis_held(-100, false, 8000));
+    }
+}
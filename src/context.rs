@@ -1,19 +1,70 @@
 #![allow(unused)]
 extern crate sdl2;
 
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
 
+use sdl2::controller::{Axis, GameController};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::render::Canvas;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
-use sdl2::Sdl;
+use sdl2::{GameControllerSubsystem, Sdl};
+
+use crate::bindings::{Action, Bindings};
+use crate::emulator::{Button, Emulator, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Window pixels per Game Boy pixel
+const SCALE: u32 = 4;
+/// RGB for each DMG shade, lightest first
+const SHADES: [[u8; 3]; 4] = [
+    [0xE0, 0xF8, 0xD0],
+    [0x88, 0xC0, 0x70],
+    [0x34, 0x68, 0x56],
+    [0x08, 0x18, 0x20],
+];
 
 pub struct SDLContext {
     context: Sdl,
     canvas: Canvas<Window>,
+    /// Streaming texture each frame is uploaded to
+    texture: Texture,
     event_pump: sdl2::EventPump,
+    game_controller: GameControllerSubsystem,
+    /// Open controllers by instance id, they close when dropped
+    controllers: HashMap<u32, GameController>,
+    bindings: Bindings,
+    /// Actions each controller is holding down, by instance id, to let go of
+    /// when it's unplugged. A button and a stick can both hold the same one.
+    controller_held: HashMap<u32, Vec<Action>>,
+    button_holders: ButtonHolders,
+    /// Stick directions currently pushed past the deadzone, by instance id
+    held_axes: HashSet<(u32, Axis, bool)>,
+    paused: bool,
+    fast_forward: bool,
+}
+
+/// How many inputs are holding down each Game Boy button. Keys, controller
+/// buttons and sticks can all be bound to the same one, it's only released
+/// once the last of them lets go.
+#[derive(Debug, Default)]
+struct ButtonHolders(HashMap<Button, u32>);
+
+impl ButtonHolders {
+    /// Counts a press or release of `button`, returning whether that changes
+    /// whether it's held
+    fn update(&mut self, button: Button, pressed: bool) -> bool {
+        let holders = self.0.entry(button).or_default();
+        if pressed {
+            *holders += 1;
+            *holders == 1
+        } else if *holders > 0 {
+            *holders -= 1;
+            *holders == 0
+        } else {
+            false
+        }
+    }
 }
 
 pub enum UpdateEvent {
@@ -21,12 +72,18 @@ pub enum UpdateEvent {
     Stop,
 }
 impl SDLContext {
-    pub fn new() -> Self {
+    pub fn new(bindings: Bindings) -> Self {
         let context = sdl2::init().unwrap();
         let video = context.video().unwrap();
+        // controllers connected at startup show up as added devices too
+        let game_controller = context.game_controller().unwrap();
 
         let window = video
-            .window("rust-sdl2 demo", 800, 600)
+            .window(
+                "RGBE",
+                SCREEN_WIDTH as u32 * SCALE,
+                SCREEN_HEIGHT as u32 * SCALE,
+            )
             .position_centered()
             .build()
             .unwrap();
@@ -36,32 +93,196 @@ impl SDLContext {
         canvas.set_draw_color(Color::RGB(255, 255, 255));
         canvas.clear();
         canvas.present();
+        let texture = canvas
+            .texture_creator()
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                SCREEN_WIDTH as u32,
+                SCREEN_HEIGHT as u32,
+            )
+            .unwrap();
         let event_pump = context.event_pump().unwrap();
         SDLContext {
             context,
             canvas,
+            texture,
             event_pump,
+            game_controller,
+            controllers: HashMap::new(),
+            bindings,
+            controller_held: HashMap::new(),
+            button_holders: ButtonHolders::default(),
+            held_axes: HashSet::new(),
+            paused: false,
+            fast_forward: false,
         }
     }
-    // TODO:
-    pub fn update(&mut self) -> UpdateEvent {
-        for event in self.event_pump.poll_iter() {
+
+    /// Handles pending window and input events, passing bound inputs on to
+    /// `emulator`
+    pub fn update(&mut self, emulator: &mut Emulator) -> UpdateEvent {
+        // collected first, handling them needs `self` back
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => return UpdateEvent::Stop,
+                // held keys repeat, which would toggle pause over and over
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(action) = self.bindings.key(keycode) {
+                        self.handle(action, true, emulator);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(action) = self.bindings.key(keycode) {
+                        self.handle(action, false, emulator);
+                    }
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match self.game_controller.open(which) {
+                        Ok(controller) => {
+                            self.controllers
+                                .insert(controller.instance_id(), controller);
+                        }
+                        Err(e) => eprintln!("Couldn't open controller {}: {}", which, e),
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.remove(&which);
+                    // nothing will release what it was holding
+                    let held = self.controller_held.remove(&which).unwrap_or_default();
+                    for action in held {
+                        self.handle(action, false, emulator);
+                    }
+                    self.held_axes.retain(|&(id, _, _)| id != which);
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(action) = self.bindings.controller_button(button) {
+                        self.controller_input(which, action, true, emulator);
+                    }
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(action) = self.bindings.controller_button(button) {
+                        self.controller_input(which, action, false, emulator);
+                    }
+                }
+                Event::ControllerAxisMotion {
+                    which, axis, value, ..
+                } => {
+                    for (positive, action, held) in self.bindings.axis(axis, value) {
+                        // only act when the stick crosses the deadzone
+                        let changed = if held {
+                            self.held_axes.insert((which, axis, positive))
+                        } else {
+                            self.held_axes.remove(&(which, axis, positive))
+                        };
+                        if changed {
+                            self.controller_input(which, action, held, emulator);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
         UpdateEvent::Continue
     }
 
-    pub fn render(&mut self) {
+    /// Emulation is paused with the pause hotkey
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// The fast-forward hotkey is held
+    pub fn fast_forward(&self) -> bool {
+        self.fast_forward
+    }
+
+    /// Handles `action` from the controller with instance id `which`,
+    /// keeping track of what it's holding
+    fn controller_input(
+        &mut self,
+        which: u32,
+        action: Action,
+        pressed: bool,
+        emulator: &mut Emulator,
+    ) {
+        let held = self.controller_held.entry(which).or_default();
+        if pressed {
+            held.push(action);
+        } else if let Some(i) = held.iter().position(|&a| a == action) {
+            held.swap_remove(i);
+        }
+        self.handle(action, pressed, emulator);
+    }
+
+    fn handle(&mut self, action: Action, pressed: bool, emulator: &mut Emulator) {
+        if let Some(button) = action.button() {
+            if !self.button_holders.update(button, pressed) {
+                return;
+            }
+            if pressed {
+                emulator.press(button);
+            } else {
+                emulator.release(button);
+            }
+            return;
+        }
+
+        match action {
+            Action::Pause if pressed => self.paused = !self.paused,
+            Action::FastForward => self.fast_forward = pressed,
+            Action::Save if pressed => {
+                if let Err(e) = emulator.save() {
+                    eprintln!("Couldn't save: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Draws a frame of DMG shades, as handed out by `Emulator::framebuffer`
+    pub fn render(&mut self, framebuffer: &[u8]) {
+        let pixels: Vec<u8> = framebuffer
+            .iter()
+            .flat_map(|&shade| SHADES[shade as usize & 0x03])
+            .collect();
+
+        self.texture
+            .update(None, &pixels, SCREEN_WIDTH * 3)
+            .unwrap();
+
         self.canvas.clear();
+        self.canvas.copy(&self.texture, None, None).unwrap();
         self.canvas.present();
+    }
+}
+
+#[cfg(test)]
+mod context_tests {
+    use super::ButtonHolders;
+    use crate::emulator::Button;
+
+    #[test]
+    fn test_button_released_by_last_holder() {
+        let mut holders = ButtonHolders::default();
 
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 5));
+        assert!(holders.update(Button::A, true));
+        assert!(!holders.update(Button::A, true));
+        assert!(!holders.update(Button::A, false));
+        assert!(holders.update(Button::A, false));
+        // a release nothing pressed, like a key held before the window
+        // had focus
+        assert!(!holders.update(Button::A, false));
+        assert!(holders.update(Button::A, true));
     }
 }
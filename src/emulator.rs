@@ -1,43 +1,230 @@
 #![allow(unused)]
+mod bus;
 mod cart;
 mod cpu;
+mod dma;
 mod instructions;
+mod interrupts;
+mod joypad;
 mod memory;
+#[cfg(test)]
+mod mooneye;
+mod ppu;
+#[cfg(test)]
+mod screenshots;
+mod timer;
 
-use cart::Cart;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bus::Bus;
+use cart::{archive, header::CartHeader, Cart, CartError};
 use cpu::CPU;
-use memory::{Memory, Partitions};
+use instructions::ExecutionError;
+
+pub use cart::rtc::ClockSource;
+pub use joypad::Button;
+pub use ppu::{Renderer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// DMG master clock, in T-cycles per second
+pub const CLOCK_SPEED: u64 = 4_194_304;
+/// T-cycles per frame: 154 scanlines of 456 cycles each
+pub const CYCLES_PER_FRAME: u64 = 70224;
+
+/// Default time between writes of battery-backed RAM to the save file
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Called with the new motor state whenever a rumble cart turns it on or off
+pub type RumbleCallback = Box<dyn FnMut(bool)>;
 
 #[derive(Default)]
 pub struct Emulator {
-    memory: Memory,
-    cart: Cart,
+    bus: Bus,
     cpu: CPU,
+    /// T-cycles elapsed since power on
+    cycles: u64,
+    rumble: bool,
+    on_rumble: Option<RumbleCallback>,
+    /// Where battery-backed RAM is kept, `None` for carts without a battery
+    save_path: Option<PathBuf>,
+    /// T-cycles between autosaves, `None` to only save when asked
+    autosave_interval: Option<u64>,
+    last_save: u64,
 }
 
 impl Emulator {
-    pub fn update(&mut self) {
-        self.cpu.execute(&mut self.memory);
+    /// Runs one instruction (or interrupt dispatch / idle cycle while halted),
+    /// returning the T-cycles it took.
+    pub fn step(&mut self) -> Result<u8, ExecutionError> {
+        let cycles = self.cpu.execute(&mut self.bus)?;
+        self.tick(cycles);
+        self.poll_rumble();
+        Ok(cycles)
+    }
+
+    /// Steps until at least `cycles` T-cycles have elapsed, returning how many
+    /// actually ran. Instructions aren't split, so this can overshoot slightly.
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<u64, ExecutionError> {
+        let start = self.cycles;
+        let target = start + cycles;
+        while self.cycles < target {
+            self.step()?;
+        }
+        Ok(self.cycles - start)
+    }
+
+    /// Runs up to the end of the current frame. Frames are aligned to the
+    /// master counter, so any overshoot is taken out of the next one.
+    pub fn run_frame(&mut self) -> Result<u64, ExecutionError> {
+        let frame_end = (self.cycles / CYCLES_PER_FRAME + 1) * CYCLES_PER_FRAME;
+        self.run_cycles(frame_end - self.cycles)
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// `SCREEN_WIDTH` x `SCREEN_HEIGHT` DMG shades from 0 (lightest) to 3
+    /// (darkest), row by row. Complete once `run_frame` returns.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.bus.ppu().framebuffer()
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.bus.press(button);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.bus.release(button);
+    }
+
+    /// Writes battery-backed RAM and the clock to the save file if they
+    /// changed since the last save. Goes through a temporary file so a crash
+    /// mid-write can't corrupt the existing save.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.save_path else {
+            return Ok(());
+        };
+        let cart = self.bus.cart_mut();
+        if !cart.needs_save() {
+            return Ok(());
+        }
+
+        let tmp = path.with_extension("sav.tmp");
+        fs::write(&tmp, cart.save_data())?;
+        fs::rename(&tmp, path)?;
+        self.last_save = self.cycles;
+        Ok(())
+    }
+
+    /// Saves if the autosave interval has passed since the last save
+    pub fn autosave(&mut self) -> io::Result<()> {
+        match self.autosave_interval {
+            Some(interval) if self.cycles - self.last_save >= interval => self.save(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Advances everything clocked alongside the CPU by `cycles` T-cycles
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.bus.tick(cycles);
+    }
+
+    fn poll_rumble(&mut self) {
+        let rumble = self.bus.rumble();
+        if rumble == self.rumble {
+            return;
+        }
+
+        self.rumble = rumble;
+        if let Some(callback) = &mut self.on_rumble {
+            callback(rumble);
+        }
     }
 }
 
 #[derive(Default)]
 pub struct EmulatorBuilder {
     cart: Cart,
-    memory: Memory,
-    cpu: CPU,
+    clock_source: ClockSource,
+    renderer: Renderer,
+    on_rumble: Option<RumbleCallback>,
+    save_path: Option<PathBuf>,
+    /// Contents of the save file, loaded into the cart on `build`
+    save_data: Option<Vec<u8>>,
+    autosave_interval: Option<Duration>,
 }
 
 impl EmulatorBuilder {
     pub fn new() -> EmulatorBuilder {
         EmulatorBuilder {
+            autosave_interval: Some(AUTOSAVE_INTERVAL),
             ..Default::default()
         }
     }
 
-    pub fn cart(mut self, file_path: String) -> EmulatorBuilder {
-        self.cart = Cart::load_rom(file_path);
-        self.memory.load_cart(self.cart.get_bank(0), Partitions::Rom0 as usize);
+    /// Loads the ROM at `file_path`, and for battery-backed carts the save
+    /// next to it with a `.sav` extension if there is one
+    pub fn cart(mut self, file_path: String) -> Result<EmulatorBuilder, CartError> {
+        self.cart = Cart::load_rom(file_path.clone())?;
+        self.load_save(&file_path)
+    }
+
+    /// Like `cart`, but with an explicit IPS, BPS or UPS patch
+    pub fn cart_with_patch(
+        mut self,
+        file_path: String,
+        patch_path: String,
+    ) -> Result<EmulatorBuilder, CartError> {
+        self.cart = Cart::load_rom_with_patch(file_path.clone(), patch_path)?;
+        self.load_save(&file_path)
+    }
+
+    fn load_save(mut self, file_path: &str) -> Result<EmulatorBuilder, CartError> {
+        let save_path = archive::unpacked_path(Path::new(file_path)).with_extension("sav");
+        if !self.cart.has_battery() {
+            return Ok(self);
+        }
+
+        self.save_data = match fs::read(&save_path) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        self.save_path = Some(save_path);
+        Ok(self)
+    }
+
+    /// How often battery-backed RAM is written out in emulated time, `None`
+    /// to only save on exit
+    pub fn autosave(mut self, interval: Option<Duration>) -> EmulatorBuilder {
+        self.autosave_interval = interval;
+        self
+    }
+
+    /// Header of the cart loaded so far
+    pub fn header(&self) -> &CartHeader {
+        self.cart.header()
+    }
+
+    /// What drives the cart's real-time clock, host time by default
+    pub fn rtc_clock(mut self, source: ClockSource) -> EmulatorBuilder {
+        self.clock_source = source;
+        self
+    }
+
+    /// How the PPU draws, the scanline renderer by default
+    pub fn renderer(mut self, renderer: Renderer) -> EmulatorBuilder {
+        self.renderer = renderer;
+        self
+    }
+
+    pub fn on_rumble(mut self, callback: impl FnMut(bool) + 'static) -> EmulatorBuilder {
+        self.on_rumble = Some(Box::new(callback));
         self
     }
 
@@ -45,10 +232,227 @@ impl EmulatorBuilder {
         let mut cpu = CPU::default();
         cpu.load_instructions();
 
+        // the clock source has to be set first, it decides whether the RTC
+        // catches up with the time spent closed
+        let mut cart = self.cart;
+        cart.set_clock_source(self.clock_source);
+        if let Some(data) = &self.save_data {
+            cart.load_save_data(data);
+        }
+
+        let mut bus = Bus::new(cart);
+        bus.ppu_mut().set_renderer(self.renderer);
+
         Emulator {
-            memory: self.memory,
-            cart: self.cart,
+            bus,
             cpu,
+            cycles: 0,
+            rumble: false,
+            on_rumble: self.on_rumble,
+            save_path: self.save_path,
+            autosave_interval: self
+                .autosave_interval
+                .map(|interval| interval.as_millis() as u64 * CLOCK_SPEED / 1000),
+            last_save: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod emulator_tests {
+    use std::{cell::RefCell, fs, io::Write, rc::Rc, time::Duration};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::{
+        cart::{header::header_checksum, mbc3::RTC_FOOTER_SIZE, Cart},
+        cpu::CpuState,
+        Button, ClockSource, Emulator, EmulatorBuilder, CLOCK_SPEED, CYCLES_PER_FRAME,
+    };
+
+    fn setup(program: &[u8]) -> Emulator {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + program.len()].copy_from_slice(program);
+
+        EmulatorBuilder {
+            cart: Cart::from_bytes(rom),
+            ..Default::default()
+        }
+        .build()
+    }
+
+    #[test]
+    fn test_step_accumulates_cycles() {
+        // NOP, LD BC,n16, JP a16
+        let mut emulator = setup(&[0x00, 0x01, 0x34, 0x12, 0xC3, 0x00, 0x01]);
+
+        assert_eq!(emulator.step().unwrap(), 4);
+        assert_eq!(emulator.step().unwrap(), 12);
+        assert_eq!(emulator.step().unwrap(), 16);
+        assert_eq!(emulator.cycles(), 32);
+    }
+
+    #[test]
+    fn test_run_frame_stays_aligned() {
+        // NOP then a 16 cycle JP loop, so frames can't end exactly on a boundary
+        let mut emulator = setup(&[0x00, 0xC3, 0x01, 0x01]);
+
+        let first = emulator.run_frame().unwrap();
+        assert_eq!(first, CYCLES_PER_FRAME + 4);
+
+        // the overshoot is taken out of the second frame rather than piling up
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.cycles(), 2 * CYCLES_PER_FRAME + 4);
+    }
+
+    #[test]
+    fn test_rumble_callback() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x50, 0x01]); // JP 0x0150
+        rom[0x0147] = 0x1C; // MBC5+RUMBLE
+        rom[0x014D] = header_checksum(&rom);
+        rom[0x0150..0x015A].copy_from_slice(&[
+            0x3E, 0x08, // LD A, 0x08
+            0xEA, 0x00, 0x40, // LD [0x4000], A
+            0x3E, 0x00, // LD A, 0x00
+            0xEA, 0x00, 0x40, // LD [0x4000], A
+        ]);
+
+        let states = Rc::new(RefCell::new(vec![]));
+        let observed = states.clone();
+        let mut emulator = EmulatorBuilder {
+            cart: Cart::new(rom).unwrap(),
+            ..Default::default()
         }
+        .on_rumble(move |on| observed.borrow_mut().push(on))
+        .build();
+
+        for _ in 0..5 {
+            emulator.step().unwrap();
+        }
+
+        assert_eq!(*states.borrow(), vec![true, false]);
+    }
+
+    #[test]
+    fn test_save_file_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rgbe-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+        let save_path = dir.join("game.sav");
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x00, 0x01]); // JP 0x0100
+        rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x0149] = 0x02; // 8 KiB
+        rom[0x014D] = header_checksum(&rom);
+        fs::write(&rom_path, &rom).unwrap();
+        let mut save = vec![0; 0x2000];
+        save[0] = 69;
+        fs::write(&save_path, &save).unwrap();
+
+        let build = || {
+            EmulatorBuilder::new()
+                .cart(rom_path.to_str().unwrap().to_string())
+                .unwrap()
+                .autosave(Some(Duration::from_secs(1)))
+                .build()
+        };
+        let mut emulator = build();
+        emulator.bus.write_u8(0x0000, 0x0A);
+        assert_eq!(emulator.bus.read_u8(0xA000), 69);
+
+        emulator.bus.write_u8(0xA001, 99);
+        emulator.autosave().unwrap();
+        assert_eq!(fs::read(&save_path).unwrap()[1], 0);
+
+        emulator.run_cycles(CLOCK_SPEED).unwrap();
+        emulator.autosave().unwrap();
+        assert_eq!(fs::read(&save_path).unwrap()[1], 99);
+
+        let mut reloaded = build();
+        reloaded.bus.write_u8(0x0000, 0x0A);
+        assert_eq!(reloaded.bus.read_u8(0xA001), 99);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_next_to_gzipped_rom() {
+        let dir = std::env::temp_dir().join(format!("rgbe-save-gz-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb.gz");
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x0149] = 0x02; // 8 KiB
+        rom[0x014D] = header_checksum(&rom);
+        let mut gz = GzEncoder::new(fs::File::create(&rom_path).unwrap(), Compression::default());
+        gz.write_all(&rom).unwrap();
+        gz.finish().unwrap();
+        let mut save = vec![0; 0x2000];
+        save[0] = 69;
+        fs::write(dir.join("game.sav"), &save).unwrap();
+
+        let mut emulator = EmulatorBuilder::new()
+            .cart(rom_path.to_str().unwrap().to_string())
+            .unwrap()
+            .build();
+        emulator.bus.write_u8(0x0000, 0x0A);
+        assert_eq!(emulator.bus.read_u8(0xA000), 69);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rtc_only_save_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rgbe-rtc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+        let save_path = dir.join("game.sav");
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x00, 0x01]); // JP 0x0100
+        rom[0x0147] = 0x0F; // MBC3+TIMER+BATTERY
+        rom[0x014D] = header_checksum(&rom);
+        fs::write(&rom_path, &rom).unwrap();
+
+        let build = || {
+            EmulatorBuilder::new()
+                .cart(rom_path.to_str().unwrap().to_string())
+                .unwrap()
+                .rtc_clock(ClockSource::Cycles)
+                .build()
+        };
+        let mut emulator = build();
+        // set the minutes register, with no RAM nothing is ever dirty
+        emulator.bus.write_u8(0x0000, 0x0A);
+        emulator.bus.write_u8(0x4000, 0x09);
+        emulator.bus.write_u8(0xA000, 42);
+        emulator.save().unwrap();
+        assert_eq!(fs::read(&save_path).unwrap().len(), RTC_FOOTER_SIZE);
+
+        let mut reloaded = build();
+        reloaded.bus.write_u8(0x0000, 0x0A);
+        reloaded.bus.write_u8(0x6000, 0x00);
+        reloaded.bus.write_u8(0x6000, 0x01);
+        reloaded.bus.write_u8(0x4000, 0x09);
+        assert_eq!(reloaded.bus.read_u8(0xA000), 42);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_button_press_wakes_stop() {
+        // STOP, INC A
+        let mut emulator = setup(&[0x10, 0x00, 0x3C]);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu.state(), CpuState::Stopped);
+
+        emulator.press(Button::Start);
+        emulator.step().unwrap();
+
+        assert_eq!(emulator.cpu.state(), CpuState::Running);
     }
 }
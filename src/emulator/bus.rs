@@ -0,0 +1,479 @@
+#![allow(unused)]
+use super::{
+    cart::Cart,
+    dma::{Dma, DMA_ADDRESS},
+    interrupts::{Interrupts, IE_ADDRESS, IF_ADDRESS},
+    joypad::{Button, Joypad, JOYPAD_ADDRESS},
+    memory::{Buffer, ReadBuffer, U16Wrapper, WriteBuffer},
+    ppu::{Ppu, BGP_ADDRESS, LCDC_ADDRESS, LYC_ADDRESS, OBP1_ADDRESS, WX_ADDRESS, WY_ADDRESS},
+    timer::{Timer, DIV_ADDRESS, TAC_ADDRESS},
+};
+
+const WRAM_START: u16 = 0xC000;
+const ECHO_START: u16 = 0xE000;
+const IO_START: u16 = 0xFF00;
+const HRAM_START: u16 = 0xFF80;
+const OAM_START: u16 = 0xFE00;
+
+/// Routes CPU reads and writes to whichever component owns the address.
+///
+/// Instructions are handed `&mut u8` references for their memory operands,
+/// but a write to ROM has to reach the cart rather than land in a byte. Those
+/// references point at a latch instead, which `commit` replays through
+/// `write_u8` once the instruction has run.
+#[derive(Default)]
+pub struct Bus {
+    cart: Cart,
+    ppu: Ppu,
+    wram: Buffer<0x2000>,
+    io: Buffer<0x80>,
+    hram: Buffer<0x7F>,
+    pub interrupts: Interrupts,
+    /// Set by the CPU while it's in STOP, which holds the divider at 0
+    pub stopped: bool,
+    joypad: Joypad,
+    timer: Timer,
+    dma: Dma,
+    latch: [u8; 2],
+    latched: [Option<u16>; 2],
+    /// Plain 64K RAM standing in for the whole map, used by the instruction
+    /// tests and conformance vectors which poke at arbitrary addresses.
+    #[cfg(test)]
+    flat: Option<Box<Buffer<0x10000>>>,
+}
+
+impl Bus {
+    pub fn new(cart: Cart) -> Self {
+        Self {
+            cart,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(test)]
+    pub fn flat() -> Self {
+        Self {
+            flat: Some(Box::default()),
+            ..Default::default()
+        }
+    }
+
+    pub fn read_u8(&self, address: u16) -> u8 {
+        #[cfg(test)]
+        if let Some(flat) = &self.flat {
+            return flat.read_u8(address);
+        }
+
+        match address {
+            // the DMA transfer has the bus
+            _ if self.dma.blocks(address) => 0xFF,
+            0x0000..=0x7FFF => self.cart.read(address),
+            // locked while the PPU is using them
+            0x8000..=0x9FFF if !self.ppu.vram_accessible() => 0xFF,
+            0x8000..=0x9FFF => self.ppu.read_vram(address),
+            0xA000..=0xBFFF => self.cart.read_ram(address),
+            0xC000..=0xDFFF => self.wram.read_u8(address - WRAM_START),
+            // echo of 0xC000-0xDDFF
+            0xE000..=0xFDFF => self.wram.read_u8(address - ECHO_START),
+            0xFE00..=0xFE9F if !self.ppu.oam_accessible() => 0xFF,
+            0xFE00..=0xFE9F => self.ppu.read_oam(address),
+            // unusable, reads back as 0 on DMG
+            0xFEA0..=0xFEFF => 0,
+            IF_ADDRESS | IE_ADDRESS => self.interrupts.read(address),
+            JOYPAD_ADDRESS => self.joypad.read(),
+            DIV_ADDRESS..=TAC_ADDRESS => self.timer.read(address),
+            DMA_ADDRESS => self.dma.read(),
+            LCDC_ADDRESS..=LYC_ADDRESS | BGP_ADDRESS..=OBP1_ADDRESS | WY_ADDRESS | WX_ADDRESS => {
+                self.ppu.read_register(address)
+            }
+            0xFF01..=0xFF7F => self.io.read_u8(address - IO_START),
+            0xFF80..=0xFFFE => self.hram.read_u8(address - HRAM_START),
+        }
+    }
+
+    pub fn write_u8(&mut self, address: u16, value: u8) {
+        #[cfg(test)]
+        if let Some(flat) = &mut self.flat {
+            return flat
+                .write_u8(address, value)
+                .expect("The flat buffer covers every address");
+        }
+
+        let result = match address {
+            _ if self.dma.blocks(address) => Ok(()),
+            0x0000..=0x7FFF => {
+                self.cart.write(address, value);
+                Ok(())
+            }
+            0x8000..=0x9FFF => {
+                if self.ppu.vram_accessible() {
+                    self.ppu.write_vram(address, value);
+                }
+                Ok(())
+            }
+            0xA000..=0xBFFF => {
+                self.cart.write_ram(address, value);
+                Ok(())
+            }
+            0xC000..=0xDFFF => self.wram.write_u8(address - WRAM_START, value),
+            0xE000..=0xFDFF => self.wram.write_u8(address - ECHO_START, value),
+            0xFE00..=0xFE9F => {
+                if self.ppu.oam_accessible() {
+                    self.ppu.write_oam(address, value);
+                }
+                Ok(())
+            }
+            0xFEA0..=0xFEFF => Ok(()),
+            IF_ADDRESS | IE_ADDRESS => {
+                self.interrupts.write(address, value);
+                Ok(())
+            }
+            JOYPAD_ADDRESS => {
+                self.joypad.write(value);
+                // selecting a row with a button held pulls its line low
+                self.interrupts.flag |= self.joypad.take_interrupts();
+                Ok(())
+            }
+            DIV_ADDRESS..=TAC_ADDRESS => {
+                self.timer.write(address, value);
+                Ok(())
+            }
+            DMA_ADDRESS => {
+                self.dma.write(value);
+                Ok(())
+            }
+            LCDC_ADDRESS..=LYC_ADDRESS | BGP_ADDRESS..=OBP1_ADDRESS | WY_ADDRESS | WX_ADDRESS => {
+                self.ppu.write_register(address, value);
+                // STAT and LYC writes can raise the STAT interrupt
+                self.interrupts.flag |= self.ppu.take_interrupts();
+                Ok(())
+            }
+            0xFF01..=0xFF7F => self.io.write_u8(address - IO_START, value),
+            0xFF80..=0xFFFE => self.hram.write_u8(address - HRAM_START, value),
+        };
+        result.expect("Every region is sized to its address range")
+    }
+
+    pub fn read_u16(&self, address: u16) -> u16 {
+        (self.read_u8(address) as u16) | ((self.read_u8(address.wrapping_add(1)) as u16) << 8)
+    }
+
+    pub fn write_u16(&mut self, address: u16, value: u16) {
+        self.write_u8(address, (value & 0x00ff) as u8);
+        self.write_u8(address.wrapping_add(1), ((value & 0xff00) >> 8) as u8);
+    }
+
+    /// Reference to the byte at `address` for an instruction to write through,
+    /// the write takes effect on the next `commit`.
+    pub fn read_u8_mut(&mut self, address: u16) -> &mut u8 {
+        self.latch = [self.read_u8(address), 0];
+        self.latched = [Some(address), None];
+        &mut self.latch[0]
+    }
+
+    /// Little endian pair at `address`, written back on the next `commit`
+    pub fn read_u16wrapper(&mut self, address: u16) -> U16Wrapper<'_> {
+        let high = address.wrapping_add(1);
+        self.latch = [self.read_u8(address), self.read_u8(high)];
+        self.latched = [Some(address), Some(high)];
+
+        let [low, high] = &mut self.latch;
+        U16Wrapper(low, high)
+    }
+
+    /// Same as `read_u16wrapper` for operands that are only read (POP, RET),
+    /// nothing is written back.
+    pub fn peek_u16wrapper(&mut self, address: u16) -> U16Wrapper<'_> {
+        self.latch = [self.read_u8(address), self.read_u8(address.wrapping_add(1))];
+        self.latched = [None, None];
+
+        let [low, high] = &mut self.latch;
+        U16Wrapper(low, high)
+    }
+
+    /// Advances the components behind the bus by `cycles` T-cycles
+    pub fn tick(&mut self, cycles: u8) {
+        self.cart.tick(cycles);
+        // the CPU only ever runs whole M-cycles
+        for _ in 0..cycles / 4 {
+            if !self.stopped {
+                self.timer.step();
+            }
+            self.step_dma();
+        }
+        self.interrupts.flag |= self.timer.take_interrupts();
+        self.ppu.tick(cycles);
+        self.interrupts.flag |= self.ppu.take_interrupts();
+    }
+
+    /// Copies the next OAM DMA byte, if a transfer is running. Sources in
+    /// 0xE000-0xFFFF see WRAM like the echo region does.
+    fn step_dma(&mut self) {
+        let Some((source, index)) = self.dma.step() else {
+            return;
+        };
+        let value = match source {
+            0x0000..=0x7FFF => self.cart.read(source),
+            0x8000..=0x9FFF => self.ppu.read_vram(source),
+            0xA000..=0xBFFF => self.cart.read_ram(source),
+            0xC000..=0xDFFF => self.wram.read_u8(source - WRAM_START),
+            _ => self.wram.read_u8(source - ECHO_START),
+        };
+        self.ppu.write_oam(OAM_START + index as u16, value);
+    }
+
+    /// Presses `button`, requesting the joypad interrupt straight away so a
+    /// stopped CPU wakes on its next step
+    pub fn press(&mut self, button: Button) {
+        self.joypad.press(button);
+        self.interrupts.flag |= self.joypad.take_interrupts();
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.joypad.release(button);
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.cart.rumble()
+    }
+
+    pub fn cart_mut(&mut self) -> &mut Cart {
+        &mut self.cart
+    }
+
+    /// Replays writes made through latched references
+    pub fn commit(&mut self) {
+        for i in 0..self.latched.len() {
+            if let Some(address) = self.latched[i].take() {
+                self.write_u8(address, self.latch[i]);
+            }
+        }
+    }
+
+    /// Drops latched writes, used when an instruction fails part way through
+    pub fn discard(&mut self) {
+        self.latched = [None, None];
+    }
+}
+
+#[cfg(test)]
+mod bus_tests {
+    use super::{Bus, Button};
+    use crate::emulator::{cart::Cart, memory::U16Wrapper};
+
+    fn rom() -> Cart {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0xFA;
+        rom[0x0001] = 0xAF;
+        rom[0x4000] = 69;
+        Cart::from_bytes(rom)
+    }
+
+    #[test]
+    fn test_read_rom() {
+        let bus = Bus::new(rom());
+
+        assert_eq!(bus.read_u8(0x4000), 69);
+        assert_eq!(bus.read_u16(0x0000), 0xAFFA);
+    }
+
+    #[test]
+    fn test_rom_writes_go_to_the_cart() {
+        let mut bus = Bus::new(rom());
+        bus.write_u8(0x0000, 0x12);
+
+        assert_eq!(bus.read_u8(0x0000), 0xFA);
+    }
+
+    #[test]
+    fn test_write_u8() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xC000, 69);
+        bus.write_u8(0x8000, 99);
+        bus.write_u8(0xFF80, 42);
+
+        assert_eq!(bus.read_u8(0xC000), 69);
+        assert_eq!(bus.read_u8(0x8000), 99);
+        assert_eq!(bus.read_u8(0xFF80), 42);
+    }
+
+    #[test]
+    fn test_write_u16() {
+        let mut bus = Bus::default();
+        bus.write_u16(0xC000, 0xAFFA);
+
+        assert_eq!(bus.read_u8(0xC000), 0xFA);
+        assert_eq!(bus.read_u8(0xC001), 0xAF);
+    }
+
+    #[test]
+    fn test_echo_ram_mirrors_wram() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xC123, 69);
+        bus.write_u8(0xFDFF, 99);
+
+        assert_eq!(bus.read_u8(0xE123), 69);
+        assert_eq!(bus.read_u8(0xDDFF), 99);
+    }
+
+    #[test]
+    fn test_unusable_range() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xFEA0, 69);
+
+        assert_eq!(bus.read_u8(0xFEA0), 0);
+    }
+
+    #[test]
+    fn test_read_u16wrapper() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xC010, 0xF0);
+        bus.write_u8(0xC011, 0x0F);
+
+        let U16Wrapper(val1, val2) = bus.read_u16wrapper(0xC010);
+
+        // sanity check values returned from function
+        assert_eq!(*val1, 0xF0);
+        assert_eq!(*val2, 0x0F);
+
+        // writes land once committed
+        *val1 = 69;
+        *val2 = 99;
+        assert_eq!(bus.read_u8(0xC010), 0xF0);
+
+        bus.commit();
+        assert_eq!(bus.read_u8(0xC010), 69);
+        assert_eq!(bus.read_u8(0xC011), 99);
+    }
+
+    #[test]
+    fn test_peek_u16wrapper_is_not_written_back() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xC010, 0xF0);
+
+        let U16Wrapper(val1, _) = bus.peek_u16wrapper(0xC010);
+        *val1 = 69;
+        bus.commit();
+
+        assert_eq!(bus.read_u8(0xC010), 0xF0);
+    }
+
+    #[test]
+    fn test_interrupt_registers() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xFFFF, 0x1F);
+        bus.write_u8(0xFF0F, 0x04);
+
+        assert_eq!(bus.interrupts.enable, 0x1F);
+        assert_eq!(bus.interrupts.flag, 0x04);
+        assert_eq!(bus.read_u8(0xFFFF), 0x1F);
+        assert_eq!(bus.read_u8(0xFF0F), 0xE4);
+    }
+
+    #[test]
+    fn test_vram_and_oam_locked_while_drawing() {
+        let mut bus = Bus::default();
+        bus.write_u8(0x8000, 69);
+
+        // OAM scan
+        assert_eq!(bus.read_u8(0xFE00), 0xFF);
+        assert_eq!(bus.read_u8(0x8000), 69);
+
+        // drawing
+        bus.tick(80);
+        bus.write_u8(0x8000, 99);
+        assert_eq!(bus.read_u8(0x8000), 0xFF);
+
+        // HBlank
+        bus.tick(200);
+        assert_eq!(bus.read_u8(0x8000), 69);
+        assert_eq!(bus.read_u8(0xFE00), 0);
+    }
+
+    #[test]
+    fn test_vblank_sets_interrupt_flag() {
+        let mut bus = Bus::default();
+        for _ in 0..144 * 456 / 4 {
+            bus.tick(4);
+        }
+
+        assert_eq!(bus.interrupts.flag & 0x01, 0x01);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut bus = Bus::default();
+        for i in 0..0xA0 {
+            bus.write_u8(0xC100 + i, i as u8 ^ 0x5A);
+        }
+        bus.write_u8(0xFF80, 69);
+
+        bus.write_u8(0xFF46, 0xC1);
+        bus.tick(8);
+
+        // only HRAM and I/O are reachable mid-transfer
+        assert_eq!(bus.read_u8(0xC100), 0xFF);
+        bus.write_u8(0xC100, 0);
+        assert_eq!(bus.read_u8(0xFF80), 69);
+        assert_eq!(bus.read_u8(0xFF46), 0xC1);
+
+        for _ in 0..0xA0 {
+            bus.tick(4);
+        }
+
+        assert_eq!(bus.read_u8(0xC100), 0x5A);
+        for i in 0..0xA0 {
+            assert_eq!(bus.ppu().read_oam(0xFE00 + i), i as u8 ^ 0x5A);
+        }
+    }
+
+    #[test]
+    fn test_oam_dma_from_echo_source() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xDE05, 69);
+
+        bus.write_u8(0xFF46, 0xFE);
+        for _ in 0..0xA1 {
+            bus.tick(4);
+        }
+
+        assert_eq!(bus.ppu().read_oam(0xFE05), 69);
+    }
+
+    #[test]
+    fn test_oam_dma_restart() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xC000, 1);
+        bus.write_u8(0xD000, 2);
+        bus.write_u8(0xD001, 3);
+
+        bus.write_u8(0xFF46, 0xC0);
+        bus.tick(4 * 10);
+        bus.write_u8(0xFF46, 0xD0);
+        for _ in 0..0xA1 {
+            bus.tick(4);
+        }
+
+        assert_eq!(bus.ppu().read_oam(0xFE00), 2);
+        assert_eq!(bus.ppu().read_oam(0xFE01), 3);
+    }
+
+    #[test]
+    fn test_joypad_interrupt() {
+        let mut bus = Bus::default();
+        bus.write_u8(0xFF00, 0x10);
+
+        bus.press(Button::A);
+
+        assert_eq!(bus.read_u8(0xFF00), 0xDE);
+        assert_eq!(bus.interrupts.flag & 0x10, 0x10);
+    }
+}
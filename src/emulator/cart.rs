@@ -1,49 +1,302 @@
 #![allow(unused)]
-use std::{fs::File, io::Read};
+pub mod archive;
+pub mod header;
+pub mod huc1;
+pub mod huc3;
+pub mod mapper;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mmm01;
+pub mod patch;
+pub mod rtc;
 
+use core::fmt;
+use std::{error::Error, fs, io, path::Path};
+
+use zip::result::ZipError;
+
+use self::{
+    header::{CartHeader, MapperKind},
+    huc1::Huc1,
+    huc3::Huc3,
+    mapper::{Mapper, RomOnly, ROM_BANK_SIZE},
+    mbc1::Mbc1,
+    mbc2::Mbc2,
+    mbc3::{Mbc3, RTC_FOOTER_SIZE},
+    mbc5::Mbc5,
+    mmm01::Mmm01,
+    patch::PatchError,
+    rtc::ClockSource,
+};
 use super::memory::Buffer;
 
-#[derive(Default)]
+#[derive(Debug)]
+pub enum CartError {
+    Io(io::Error),
+    Zip(ZipError),
+    /// A zip with no `.gb` or `.gbc` entry
+    NoRomInArchive,
+    Patch(PatchError),
+    /// The image is shorter than its header says it should be
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    HeaderChecksum {
+        expected: u8,
+        actual: u8,
+    },
+    UnknownCartType(u8),
+    InvalidRomSize(u8),
+    InvalidRamSize(u8),
+    UnsupportedMapper(MapperKind),
+}
+
+impl fmt::Display for CartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Couldn't read ROM: {}", e),
+            Self::Zip(e) => write!(f, "Couldn't unzip ROM: {}", e),
+            Self::NoRomInArchive => write!(f, "No .gb or .gbc file in the archive"),
+            Self::Patch(e) => write!(f, "Couldn't patch ROM: {}", e),
+            Self::Truncated { expected, actual } => write!(
+                f,
+                "ROM is truncated, expected {:#x} bytes but got {:#x}",
+                expected, actual
+            ),
+            Self::HeaderChecksum { expected, actual } => write!(
+                f,
+                "Header checksum mismatch, header says {:#04x} but computed {:#04x}",
+                expected, actual
+            ),
+            Self::UnknownCartType(code) => write!(f, "Unknown cartridge type {:#04x}", code),
+            Self::InvalidRomSize(code) => write!(f, "Invalid ROM size code {:#04x}", code),
+            Self::InvalidRamSize(code) => write!(f, "Invalid RAM size code {:#04x}", code),
+            Self::UnsupportedMapper(kind) => write!(f, "{:?} carts aren't supported yet", kind),
+        }
+    }
+}
+
+impl Error for CartError {}
+
+impl From<io::Error> for CartError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ZipError> for CartError {
+    fn from(e: ZipError) -> Self {
+        Self::Zip(e)
+    }
+}
+
+impl From<PatchError> for CartError {
+    fn from(e: PatchError) -> Self {
+        Self::Patch(e)
+    }
+}
+
+/// Patches picked up automatically when they sit next to the ROM
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "bps", "ups"];
+
 pub struct Cart {
     buf: Vec<u8>,
+    /// External RAM mapped at 0xA000-0xBFFF, empty when the cart has none
+    ram: Vec<u8>,
+    header: CartHeader,
+    mapper: Box<dyn Mapper>,
+    /// RAM has been written since it was last saved, writes while RAM is
+    /// disabled or the clock is mapped in don't count
+    ram_dirty: bool,
+}
+
+impl Default for Cart {
+    fn default() -> Self {
+        Self {
+            buf: vec![],
+            ram: vec![],
+            header: CartHeader::default(),
+            mapper: Box::new(RomOnly),
+            ram_dirty: false,
+        }
+    }
 }
 
 impl Cart {
-    pub fn load_rom(rom_path: String) -> Self {
-        let mut f = File::open(&rom_path).expect("File not found");
-        let mut buf: Vec<u8> = vec![];
-        let size = f.read_to_end(&mut buf).expect("Error reading file");
+    /// Loads a ROM, zipped or gzipped, applying a patch with the same name
+    /// and an `.ips`, `.bps` or `.ups` extension if there is one
+    pub fn load_rom(rom_path: String) -> Result<Self, CartError> {
+        let rom_path = Path::new(&rom_path);
+        let unpacked_path = archive::unpacked_path(rom_path);
+        let patch_path = PATCH_EXTENSIONS
+            .iter()
+            .map(|ext| unpacked_path.with_extension(ext))
+            .find(|path| path.is_file());
+        Self::load_patched(rom_path, patch_path.as_deref())
+    }
 
-        Cart { buf }
+    pub fn load_rom_with_patch(rom_path: String, patch_path: String) -> Result<Self, CartError> {
+        Self::load_patched(Path::new(&rom_path), Some(Path::new(&patch_path)))
     }
 
-    pub fn get_bank(&self, start: u16) -> Buffer<0x4000> {
-        Buffer {
-            buf: (self.buf[(start as usize)..(start as usize + 0x4000)])
-                .try_into()
-                .unwrap(),
+    fn load_patched(rom_path: &Path, patch_path: Option<&Path>) -> Result<Self, CartError> {
+        let mut buf = archive::read_rom(rom_path)?;
+        if let Some(patch_path) = patch_path {
+            buf = patch::apply(&buf, &fs::read(patch_path)?)?;
         }
+        Self::new(buf)
+    }
+
+    /// Validates `buf` as a ROM image and sizes external RAM from its header
+    pub fn new(buf: Vec<u8>) -> Result<Self, CartError> {
+        let header = CartHeader::parse(&buf)?;
+        if buf.len() < header.rom_size {
+            return Err(CartError::Truncated {
+                expected: header.rom_size,
+                actual: buf.len(),
+            });
+        }
+
+        let mapper: Box<dyn Mapper> = match header.cart_type.mapper {
+            MapperKind::RomOnly => Box::new(RomOnly),
+            MapperKind::Mbc1 => Box::new(Mbc1::new(&buf)),
+            MapperKind::Mbc2 => Box::new(Mbc2::default()),
+            MapperKind::Mbc3 => Box::new(Mbc3::new(header.cart_type.timer)),
+            MapperKind::Mbc5 => Box::new(Mbc5::new(header.cart_type.rumble)),
+            MapperKind::Mmm01 => Box::new(Mmm01::default()),
+            MapperKind::HuC1 => Box::new(Huc1::default()),
+            MapperKind::HuC3 => Box::new(Huc3::default()),
+            kind => return Err(CartError::UnsupportedMapper(kind)),
+        };
+
+        // MBC2 RAM is inside the mapper, the header reports none
+        let ram_size = match header.cart_type.mapper {
+            MapperKind::Mbc2 => mbc2::RAM_SIZE,
+            _ => header.ram_size,
+        };
+
+        Ok(Cart {
+            ram: vec![0; ram_size],
+            buf,
+            header,
+            mapper,
+            ram_dirty: false,
+        })
+    }
+
+    /// Raw image with no header checks, for tests that only care about the
+    /// address mapping.
+    #[cfg(test)]
+    pub fn from_bytes(buf: Vec<u8>) -> Self {
+        Cart {
+            buf,
+            ..Default::default()
+        }
+    }
+
+    pub fn header(&self) -> &CartHeader {
+        &self.header
+    }
+
+    pub fn get_bank(&self, start: u16) -> Result<Buffer<ROM_BANK_SIZE>, CartError> {
+        let start = start as usize;
+        let bank = self
+            .buf
+            .get(start..start + ROM_BANK_SIZE)
+            .ok_or(CartError::Truncated {
+                expected: start + ROM_BANK_SIZE,
+                actual: self.buf.len(),
+            })?;
+
+        Ok(Buffer {
+            buf: bank.try_into().unwrap(),
+        })
     }
 
     pub fn read(&self, address: u16) -> u8 {
-        if address as usize > self.buf.len() {
-            return 0;
+        self.mapper.read_rom(&self.buf, address)
+    }
+
+    /// Writes into ROM space go to the mapper's registers
+    pub fn write(&mut self, address: u16, value: u8) {
+        self.mapper.write_rom(address, value)
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.mapper.read_ram(&self.ram, address)
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if self.mapper.write_ram(&mut self.ram, address, value) {
+            self.ram_dirty = true;
+        }
+    }
+
+    /// Whether RAM (and the clock, if any) survive power off
+    pub fn has_battery(&self) -> bool {
+        self.header.cart_type.battery
+    }
+
+    pub fn ram_dirty(&self) -> bool {
+        self.ram_dirty
+    }
+
+    /// Whether the save file is out of date. The clock keeps running
+    /// without any writes, so carts with one always need saving.
+    pub fn needs_save(&self) -> bool {
+        self.ram_dirty || self.mapper.has_rtc()
+    }
+
+    /// Raw RAM followed by the RTC footer if the cart has a clock, the same
+    /// layout other emulators use so saves can move between them
+    pub fn save_data(&mut self) -> Vec<u8> {
+        self.ram_dirty = false;
+        let mut data = self.ram.clone();
+        if let Some(footer) = self.mapper.rtc_footer() {
+            data.extend(footer);
         }
-        self.buf[address as usize]
+        data
+    }
+
+    /// Loads a save written by `save_data`. Short saves only fill the start of
+    /// RAM, anything past the end of RAM is taken as an RTC footer.
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+        if data.len() > self.ram.len() {
+            self.mapper.load_rtc_footer(&data[self.ram.len()..]);
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        self.mapper.tick(cycles)
+    }
+
+    pub fn set_clock_source(&mut self, source: ClockSource) {
+        self.mapper.set_clock_source(source)
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.mapper.rumble()
     }
 }
 
 #[cfg(test)]
 mod cart_tests {
-    use super::Cart;
+    use std::{fs, io::Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::{header, header::MapperKind, Cart, CartError, RTC_FOOTER_SIZE};
 
     #[test]
     fn test_size_get_bank() {
-        let test_cart = Cart {
-            buf: vec![0; u16::MAX as usize],
-        };
+        let test_cart = Cart::from_bytes(vec![0; u16::MAX as usize]);
 
-        let bank = test_cart.get_bank(0x100);
+        let bank = test_cart.get_bank(0x100).unwrap();
 
         assert_eq!(bank.buf.len(), 0x4000)
     }
@@ -51,18 +304,174 @@ mod cart_tests {
     #[test]
     fn test_content_get_bank() {
         let start_idx = 100;
-        let mut test_cart = Cart {
-            buf: vec![0; u16::MAX as usize],
-        };
+        let mut test_cart = Cart::from_bytes(vec![0; u16::MAX as usize]);
 
         test_cart.buf[start_idx + 1] = 0xAA;
         test_cart.buf[start_idx + 2] = 0xBB;
         test_cart.buf[start_idx + 0x3FFF] = 0xCC;
 
-        let bank = test_cart.get_bank(start_idx as u16);
+        let bank = test_cart.get_bank(start_idx as u16).unwrap();
 
         assert_eq!(bank.buf[1], 0xAA);
         assert_eq!(bank.buf[2], 0xBB);
         assert_eq!(bank.buf[0x3FFF], 0xCC);
     }
+
+    #[test]
+    fn test_get_bank_past_end() {
+        let test_cart = Cart::from_bytes(vec![0; 0x4000]);
+
+        assert!(matches!(
+            test_cart.get_bank(0x100),
+            Err(CartError::Truncated {
+                expected: 0x4100,
+                actual: 0x4000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_new_sizes_ram_from_header() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0149] = 0x03;
+        rom[0x014D] = header::header_checksum(&rom);
+
+        let cart = Cart::new(rom).unwrap();
+
+        assert_eq!(cart.ram.len(), 0x8000);
+    }
+
+    #[test]
+    fn test_new_gives_mbc2_built_in_ram() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x06; // MBC2+BATTERY
+        rom[0x014D] = header::header_checksum(&rom);
+
+        let cart = Cart::new(rom).unwrap();
+
+        assert_eq!(cart.ram.len(), 0x200);
+    }
+
+    #[test]
+    fn test_save_data_round_trip() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x10; // MBC3+TIMER+RAM+BATTERY
+        rom[0x0149] = 0x02; // 8 KiB
+        rom[0x014D] = header::header_checksum(&rom);
+        let mut cart = Cart::new(rom.clone()).unwrap();
+        assert!(cart.has_battery());
+
+        cart.write(0x0000, 0x0A);
+        cart.write_ram(0xA010, 69);
+        assert!(cart.ram_dirty());
+
+        let save = cart.save_data();
+        assert!(!cart.ram_dirty());
+        assert_eq!(save.len(), 0x2000 + RTC_FOOTER_SIZE);
+        assert_eq!(save[0x10], 69);
+
+        let mut loaded = Cart::new(rom).unwrap();
+        loaded.load_save_data(&save);
+        loaded.write(0x0000, 0x0A);
+        assert_eq!(loaded.read_ram(0xA010), 69);
+    }
+
+    #[test]
+    fn test_disabled_ram_writes_stay_clean() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x0149] = 0x02; // 8 KiB
+        rom[0x014D] = header::header_checksum(&rom);
+        let mut cart = Cart::new(rom).unwrap();
+
+        cart.write_ram(0xA000, 69);
+        assert!(!cart.ram_dirty());
+        assert!(!cart.needs_save());
+
+        cart.write(0x0000, 0x0A);
+        cart.write_ram(0xA000, 69);
+        assert!(cart.needs_save());
+    }
+
+    #[test]
+    fn test_load_rom_applies_patch_next_to_it() {
+        let dir = std::env::temp_dir().join(format!("rgbe-patch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb");
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x014D] = header::header_checksum(&rom);
+        fs::write(&rom_path, &rom).unwrap();
+        // write 69 to 0x0200
+        let mut ips = b"PATCH\x00\x02\x00\x00\x01\x45".to_vec();
+        ips.extend(b"EOF");
+        fs::write(dir.join("game.ips"), &ips).unwrap();
+
+        let cart = Cart::load_rom(rom_path.to_str().unwrap().to_string());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cart.unwrap().read(0x0200), 69);
+    }
+
+    #[test]
+    fn test_load_rom_applies_patch_next_to_gzipped_rom() {
+        let dir = std::env::temp_dir().join(format!("rgbe-patch-gz-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.gb.gz");
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x014D] = header::header_checksum(&rom);
+        let mut gz = GzEncoder::new(fs::File::create(&rom_path).unwrap(), Compression::default());
+        gz.write_all(&rom).unwrap();
+        gz.finish().unwrap();
+        // write 69 to 0x0200
+        let mut ips = b"PATCH\x00\x02\x00\x00\x01\x45".to_vec();
+        ips.extend(b"EOF");
+        fs::write(dir.join("game.ips"), &ips).unwrap();
+
+        let cart = Cart::load_rom(rom_path.to_str().unwrap().to_string());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cart.unwrap().read(0x0200), 69);
+    }
+
+    #[test]
+    fn test_new_rejects_short_image() {
+        let mut rom = vec![0; 0x4000];
+        rom[0x014D] = header::header_checksum(&rom);
+
+        assert!(matches!(
+            Cart::new(rom),
+            Err(CartError::Truncated {
+                expected: 0x8000,
+                actual: 0x4000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_new_picks_mapper_from_header() {
+        let mut rom = vec![0; 0x10000];
+        rom[0x0147] = 0x01; // MBC1
+        rom[0x0148] = 0x01; // 64 KiB
+        rom[0x014D] = header::header_checksum(&rom);
+        rom[0x8000] = 69;
+
+        let mut cart = Cart::new(rom).unwrap();
+        cart.write(0x2000, 2);
+
+        assert_eq!(cart.read(0x4000), 69);
+    }
+
+    #[test]
+    fn test_new_rejects_unsupported_mapper() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0147] = 0x20; // MBC6
+        rom[0x014D] = header::header_checksum(&rom);
+
+        assert!(matches!(
+            Cart::new(rom),
+            Err(CartError::UnsupportedMapper(MapperKind::Mbc6))
+        ));
+    }
 }
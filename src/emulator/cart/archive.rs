@@ -0,0 +1,116 @@
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use super::CartError;
+
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/// `path` with a `.zip` or `.gz` extension dropped, so `game.gb.gz` gives
+/// `game.gb`. Files that go with a ROM are named after this.
+pub fn unpacked_path(path: &Path) -> PathBuf {
+    if has_extension(path, &["zip", "gz"]) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Reads a ROM image, unpacking it first if `path` is a `.zip` or `.gz`.
+/// Zips can hold anything, the first `.gb`/`.gbc` entry is taken.
+pub fn read_rom(path: &Path) -> Result<Vec<u8>, CartError> {
+    let mut buf = vec![];
+    if has_extension(path, &["zip"]) {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let name = archive
+            .file_names()
+            .find(|name| has_extension(Path::new(name), &ROM_EXTENSIONS))
+            .ok_or(CartError::NoRomInArchive)?
+            .to_string();
+        archive.by_name(&name)?.read_to_end(&mut buf)?;
+    } else if has_extension(path, &["gz"]) {
+        GzDecoder::new(File::open(path)?).read_to_end(&mut buf)?;
+    } else {
+        buf = fs::read(path)?;
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use std::{
+        fs::{self, File},
+        io::Write,
+        path::PathBuf,
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::read_rom;
+    use crate::emulator::cart::CartError;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rgbe-archive-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_zip_picks_first_rom() {
+        let path = temp_path("roms.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("README.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"not a rom").unwrap();
+        zip.start_file("Game.GBC", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&[1, 2, 3]).unwrap();
+        zip.start_file("other.gb", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&[4, 5, 6]).unwrap();
+        zip.finish().unwrap();
+
+        let rom = read_rom(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(rom.unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_zip_without_rom() {
+        let path = temp_path("empty.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("README.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.finish().unwrap();
+
+        let rom = read_rom(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(rom, Err(CartError::NoRomInArchive)));
+    }
+
+    #[test]
+    fn test_gzip() {
+        let path = temp_path("game.gb.gz");
+        let mut gz = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        gz.write_all(&[1, 2, 3]).unwrap();
+        gz.finish().unwrap();
+
+        let rom = read_rom(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(rom.unwrap(), [1, 2, 3]);
+    }
+}
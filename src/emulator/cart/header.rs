@@ -0,0 +1,364 @@
+use super::CartError;
+
+pub const HEADER_END: usize = 0x0150;
+
+const TITLE: usize = 0x0134;
+const MANUFACTURER: usize = 0x013F;
+const CGB_FLAG: usize = 0x0143;
+const NEW_LICENSEE: usize = 0x0144;
+const SGB_FLAG: usize = 0x0146;
+const CART_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const DESTINATION: usize = 0x014A;
+const OLD_LICENSEE: usize = 0x014B;
+const VERSION: usize = 0x014C;
+const HEADER_CHECKSUM: usize = 0x014D;
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+/// Old licensee value meaning the new licensee code should be used instead
+const USE_NEW_LICENSEE: u8 = 0x33;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CgbSupport {
+    #[default]
+    None,
+    /// Runs on DMG too, with CGB enhancements
+    Enhanced,
+    Only,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Licensee {
+    Old(u8),
+    New(String),
+}
+
+impl Default for Licensee {
+    fn default() -> Self {
+        Licensee::Old(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Destination {
+    #[default]
+    Japan,
+    Overseas,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapperKind {
+    #[default]
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mmm01,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1,
+}
+
+/// Decoded cartridge type byte (0x0147): the mapper plus the extra hardware
+/// wired up alongside it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CartType {
+    pub code: u8,
+    pub mapper: MapperKind,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartType {
+    pub fn decode(code: u8) -> Result<Self, CartError> {
+        use MapperKind::*;
+
+        // (mapper, ram, battery, timer, rumble)
+        let (mapper, ram, battery, timer, rumble) = match code {
+            0x00 => (RomOnly, false, false, false, false),
+            0x01 => (Mbc1, false, false, false, false),
+            0x02 => (Mbc1, true, false, false, false),
+            0x03 => (Mbc1, true, true, false, false),
+            0x05 => (Mbc2, false, false, false, false),
+            0x06 => (Mbc2, false, true, false, false),
+            0x08 => (RomOnly, true, false, false, false),
+            0x09 => (RomOnly, true, true, false, false),
+            0x0B => (Mmm01, false, false, false, false),
+            0x0C => (Mmm01, true, false, false, false),
+            0x0D => (Mmm01, true, true, false, false),
+            0x0F => (Mbc3, false, true, true, false),
+            0x10 => (Mbc3, true, true, true, false),
+            0x11 => (Mbc3, false, false, false, false),
+            0x12 => (Mbc3, true, false, false, false),
+            0x13 => (Mbc3, true, true, false, false),
+            0x19 => (Mbc5, false, false, false, false),
+            0x1A => (Mbc5, true, false, false, false),
+            0x1B => (Mbc5, true, true, false, false),
+            0x1C => (Mbc5, false, false, false, true),
+            0x1D => (Mbc5, true, false, false, true),
+            0x1E => (Mbc5, true, true, false, true),
+            0x20 => (Mbc6, true, true, false, false),
+            0x22 => (Mbc7, true, true, false, true),
+            0xFC => (PocketCamera, true, true, false, false),
+            0xFD => (Tama5, true, true, true, false),
+            0xFE => (HuC3, true, true, true, false),
+            0xFF => (HuC1, true, true, false, false),
+            _ => return Err(CartError::UnknownCartType(code)),
+        };
+
+        Ok(Self {
+            code,
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+/// Cartridge header, decoded from 0x0100-0x014F of the ROM image.
+#[derive(Debug, Clone, Default)]
+pub struct CartHeader {
+    pub title: String,
+    /// Only present on later CGB carts, where it takes the end of the title
+    pub manufacturer: Option<String>,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    pub licensee: Licensee,
+    pub cart_type: CartType,
+    /// In bytes
+    pub rom_size: usize,
+    /// In bytes, excluding RAM built into the mapper (MBC2)
+    pub ram_size: usize,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    /// Whether the global checksum matches the ROM. The boot ROM never
+    /// checks it and patched images rarely fix it up, so a mismatch isn't
+    /// fatal.
+    pub global_checksum_ok: bool,
+}
+
+impl CartHeader {
+    pub fn parse(rom: &[u8]) -> Result<Self, CartError> {
+        if rom.len() < HEADER_END {
+            return Err(CartError::Truncated {
+                expected: HEADER_END,
+                actual: rom.len(),
+            });
+        }
+
+        let actual = header_checksum(rom);
+        if actual != rom[HEADER_CHECKSUM] {
+            return Err(CartError::HeaderChecksum {
+                expected: rom[HEADER_CHECKSUM],
+                actual,
+            });
+        }
+
+        let cgb = match rom[CGB_FLAG] {
+            0xC0 => CgbSupport::Only,
+            flag if flag & 0x80 != 0 => CgbSupport::Enhanced,
+            _ => CgbSupport::None,
+        };
+
+        // CGB carts shortened the title to make room for the manufacturer
+        // code and CGB flag, older ones use all 16 bytes.
+        let (title_end, manufacturer) = match cgb {
+            CgbSupport::None => (CGB_FLAG + 1, None),
+            _ => {
+                let code = &rom[MANUFACTURER..CGB_FLAG];
+                let manufacturer = code
+                    .iter()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+                    .then(|| ascii(code));
+                let title_end = if manufacturer.is_some() {
+                    MANUFACTURER
+                } else {
+                    CGB_FLAG
+                };
+                (title_end, manufacturer)
+            }
+        };
+
+        let licensee = match rom[OLD_LICENSEE] {
+            USE_NEW_LICENSEE => Licensee::New(ascii(&rom[NEW_LICENSEE..NEW_LICENSEE + 2])),
+            code => Licensee::Old(code),
+        };
+
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            code => return Err(CartError::InvalidRomSize(code)),
+        };
+
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            // unofficial, listed by some homebrew
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(CartError::InvalidRamSize(code)),
+        };
+
+        let header = Self {
+            title: ascii(&rom[TITLE..title_end]),
+            manufacturer,
+            cgb,
+            sgb: rom[SGB_FLAG] == 0x03,
+            licensee,
+            cart_type: CartType::decode(rom[CART_TYPE])?,
+            rom_size,
+            ram_size,
+            destination: match rom[DESTINATION] {
+                0x00 => Destination::Japan,
+                _ => Destination::Overseas,
+            },
+            version: rom[VERSION],
+            header_checksum: rom[HEADER_CHECKSUM],
+            global_checksum: u16::from_be_bytes([rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]]),
+            global_checksum_ok: false,
+        };
+
+        Ok(CartHeader {
+            global_checksum_ok: global_checksum(rom) == header.global_checksum,
+            ..header
+        })
+    }
+}
+
+/// Checksum over 0x0134-0x014C, the boot ROM locks up if it doesn't match
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1))
+}
+
+/// Sum of every byte in the image except the global checksum itself
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(i, _)| *i != GLOBAL_CHECKSUM && *i != GLOBAL_CHECKSUM + 1)
+        .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte as u16))
+}
+
+/// Header strings are NUL padded ASCII
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| if c.is_ascii() { c as char } else { '?' })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(test)]
+mod header_tests {
+    use super::{
+        global_checksum, header_checksum, CartHeader, CgbSupport, Destination, Licensee,
+        MapperKind, GLOBAL_CHECKSUM, HEADER_CHECKSUM,
+    };
+    use crate::emulator::cart::CartError;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0134 + 8].copy_from_slice(b"CPU_INST");
+        rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+        rom[0x0149] = 0x02;
+        rom[0x014A] = 0x01;
+        rom[0x014B] = 0x33;
+        rom[0x0144..0x0146].copy_from_slice(b"01");
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    fn fix_checksums(rom: &mut [u8]) {
+        rom[HEADER_CHECKSUM] = header_checksum(rom);
+        let global = global_checksum(rom).to_be_bytes();
+        rom[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&global);
+    }
+
+    #[test]
+    fn test_parse() {
+        let rom = rom();
+        let header = CartHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "CPU_INST");
+        assert_eq!(header.manufacturer, None);
+        assert_eq!(header.cgb, CgbSupport::None);
+        assert_eq!(header.licensee, Licensee::New(String::from("01")));
+        assert_eq!(header.cart_type.mapper, MapperKind::Mbc1);
+        assert!(header.cart_type.battery);
+        assert_eq!(header.rom_size, 0x8000);
+        assert_eq!(header.ram_size, 0x2000);
+        assert_eq!(header.destination, Destination::Overseas);
+        assert!(header.global_checksum_ok);
+    }
+
+    #[test]
+    fn test_cgb_title_and_manufacturer() {
+        let mut rom = rom();
+        rom[0x0134..0x0144].copy_from_slice(b"POKEMON_SLVAAXE\x80");
+        fix_checksums(&mut rom);
+
+        let header = CartHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.cgb, CgbSupport::Enhanced);
+        assert_eq!(header.title, "POKEMON_SLV");
+        assert_eq!(header.manufacturer, Some(String::from("AAXE")));
+    }
+
+    #[test]
+    fn test_header_checksum_mismatch() {
+        let mut rom = rom();
+        rom[0x0134] = b'X';
+
+        assert!(matches!(
+            CartHeader::parse(&rom),
+            Err(CartError::HeaderChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn test_global_checksum_mismatch_is_reported() {
+        let mut rom = rom();
+        rom[0x4000] = 0xFF;
+
+        let header = CartHeader::parse(&rom).unwrap();
+        assert!(!header.global_checksum_ok);
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert!(matches!(
+            CartHeader::parse(&[0; 0x100]),
+            Err(CartError::Truncated {
+                expected: 0x150,
+                actual: 0x100
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_cart_type() {
+        let mut rom = rom();
+        rom[0x0147] = 0x04;
+        fix_checksums(&mut rom);
+
+        assert!(matches!(
+            CartHeader::parse(&rom),
+            Err(CartError::UnknownCartType(0x04))
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use super::mapper::{banked, banked_mut, Mapper, RAM_BANK_SIZE, RAM_START, ROM_BANK_SIZE};
+
+/// Written to 0x0000-0x1FFF to map the IR port over cart RAM
+const IR_SELECT: u8 = 0x0E;
+/// What the IR port reads as with no light coming in
+const IR_NO_LIGHT: u8 = 0xC0;
+
+/// Hudson's HuC1: MBC1 style banking with an infrared LED and receiver that
+/// can be mapped in place of RAM.
+#[derive(Debug)]
+pub struct Huc1 {
+    ir_mode: bool,
+    /// 6-bit register at 0x2000-0x3FFF
+    rom_bank: u8,
+    ram_bank: u8,
+    ir_led: bool,
+}
+
+impl Default for Huc1 {
+    fn default() -> Self {
+        Self {
+            ir_mode: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            ir_led: false,
+        }
+    }
+}
+
+impl Huc1 {
+    pub fn ir_led(&self) -> bool {
+        self.ir_led
+    }
+}
+
+impl Mapper for Huc1 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => banked(rom, 0, ROM_BANK_SIZE, address as usize),
+            _ => banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (address & 0x3FFF) as usize,
+            ),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_mode = value & 0x0F == IR_SELECT,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0x3F {
+                    0 => 1,
+                    bank => bank,
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if self.ir_mode {
+            return IR_NO_LIGHT;
+        }
+        banked(
+            ram,
+            self.ram_bank as usize,
+            RAM_BANK_SIZE,
+            (address - RAM_START) as usize,
+        )
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if self.ir_mode {
+            self.ir_led = value & 0x01 != 0;
+            return false;
+        }
+        let offset = (address - RAM_START) as usize;
+        match banked_mut(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset) {
+            Some(x) => {
+                *x = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod huc1_tests {
+    use super::Huc1;
+    use crate::emulator::cart::mapper::{Mapper, ROM_BANK_SIZE};
+
+    #[test]
+    fn test_banking() {
+        let mut rom = vec![0; 64 * ROM_BANK_SIZE];
+        rom[0x3F * ROM_BANK_SIZE] = 69;
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Huc1::default();
+
+        mbc.write_rom(0x2000, 0x3F);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 69);
+
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0xA000, 99);
+        assert_eq!(ram[2 * 0x2000], 99);
+    }
+
+    #[test]
+    fn test_ir_register_replaces_ram() {
+        let mut ram = vec![0; 0x2000];
+        let mut mbc = Huc1::default();
+
+        mbc.write_rom(0x0000, 0x0E);
+        mbc.write_ram(&mut ram, 0xA000, 0x01);
+
+        assert!(mbc.ir_led());
+        assert_eq!(ram[0], 0);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xC0);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0);
+    }
+}
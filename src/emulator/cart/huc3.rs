@@ -0,0 +1,310 @@
+use super::{
+    mapper::{banked, banked_mut, Mapper, RAM_BANK_SIZE, RAM_START, ROM_BANK_SIZE},
+    rtc::{Clock, ClockSource},
+};
+
+/// Values written to 0x0000-0x1FFF, deciding what 0xA000-0xBFFF maps to
+const MODE_RAM_READ: u8 = 0x00;
+const MODE_RAM: u8 = 0x0A;
+const MODE_RTC_COMMAND: u8 = 0x0B;
+const MODE_RTC_RESPONSE: u8 = 0x0C;
+const MODE_RTC_SEMAPHORE: u8 = 0x0D;
+const MODE_IR: u8 = 0x0E;
+
+/// What the IR port reads as with no light coming in
+const IR_NO_LIGHT: u8 = 0xC0;
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// The HuC3 clock, driven through 4-bit commands. Time is kept as minutes
+/// into the day and a day counter, and games move it in and out of a small
+/// nibble-addressed memory.
+#[derive(Debug)]
+struct Huc3Rtc {
+    clock: Clock,
+    /// Seconds counted towards the next minute
+    seconds: u64,
+    /// 12-bit, wraps at a day
+    minutes: u16,
+    /// 16-bit
+    days: u16,
+    /// One nibble per entry, 0x00-0x06 holds the time when copied out
+    memory: [u8; 0x100],
+    address: u8,
+    command: u8,
+    response: u8,
+}
+
+impl Default for Huc3Rtc {
+    fn default() -> Self {
+        Self {
+            clock: Clock::default(),
+            seconds: 0,
+            minutes: 0,
+            days: 0,
+            memory: [0; 0x100],
+            address: 0,
+            command: 0,
+            response: 0,
+        }
+    }
+}
+
+impl Huc3Rtc {
+    /// Catches the counters up with the clock
+    fn update(&mut self) {
+        let seconds = self.seconds + self.clock.elapsed_seconds();
+        self.seconds = seconds % 60;
+
+        let minutes = self.minutes as u64 + seconds / 60;
+        self.minutes = (minutes % MINUTES_PER_DAY as u64) as u16;
+        self.days = self
+            .days
+            .wrapping_add((minutes / MINUTES_PER_DAY as u64) as u16);
+    }
+
+    /// Runs a command written in RTC command mode, high nibble is the command
+    /// and low nibble its argument
+    fn execute(&mut self, value: u8) {
+        let command = (value >> 4) & 0x07;
+        let argument = value & 0x0F;
+        self.command = command;
+
+        match command {
+            0x1 => {
+                self.response = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x4 => self.address = (self.address & 0xF0) | argument,
+            0x5 => self.address = (self.address & 0x0F) | argument << 4,
+            0x6 => match argument {
+                0x0 => self.copy_out(),
+                0x1 => self.copy_in(),
+                // status, always ready
+                0x2 => self.response = 0x01,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Writes the current time into memory 0x00-0x06, low nibble first
+    fn copy_out(&mut self) {
+        self.update();
+        for i in 0..3 {
+            self.memory[i] = (self.minutes >> (4 * i)) as u8 & 0x0F;
+        }
+        for i in 0..4 {
+            self.memory[3 + i] = (self.days >> (4 * i)) as u8 & 0x0F;
+        }
+    }
+
+    /// Sets the time from memory 0x00-0x06
+    fn copy_in(&mut self) {
+        let nibbles = |range: std::ops::Range<usize>| {
+            self.memory[range]
+                .iter()
+                .rev()
+                .fold(0u16, |acc, &nibble| acc << 4 | nibble as u16)
+        };
+        self.minutes = nibbles(0..3) % MINUTES_PER_DAY;
+        self.days = nibbles(3..7);
+        self.seconds = 0;
+        self.clock.reset_subsecond();
+    }
+}
+
+/// Hudson's HuC3: ROM and RAM banking plus a real-time clock and an
+/// infrared port, both reached through a mode register in place of RAM.
+#[derive(Debug)]
+pub struct Huc3 {
+    mode: u8,
+    /// 7-bit register at 0x2000-0x3FFF
+    rom_bank: u8,
+    ram_bank: u8,
+    rtc: Huc3Rtc,
+    ir_led: bool,
+}
+
+impl Default for Huc3 {
+    fn default() -> Self {
+        Self {
+            mode: MODE_RAM_READ,
+            rom_bank: 1,
+            ram_bank: 0,
+            rtc: Huc3Rtc::default(),
+            ir_led: false,
+        }
+    }
+}
+
+impl Huc3 {
+    pub fn ir_led(&self) -> bool {
+        self.ir_led
+    }
+}
+
+impl Mapper for Huc3 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => banked(rom, 0, ROM_BANK_SIZE, address as usize),
+            _ => banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (address & 0x3FFF) as usize,
+            ),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.mode = value & 0x0F,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0x7F {
+                    0 => 1,
+                    bank => bank,
+                }
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        match self.mode {
+            MODE_RAM_READ | MODE_RAM => banked(
+                ram,
+                self.ram_bank as usize,
+                RAM_BANK_SIZE,
+                (address - RAM_START) as usize,
+            ),
+            MODE_RTC_RESPONSE => 0x80 | self.rtc.command << 4 | self.rtc.response,
+            // commands finish instantly, so the clock is always ready
+            MODE_RTC_SEMAPHORE => 0x01,
+            MODE_IR => IR_NO_LIGHT,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        match self.mode {
+            MODE_RAM => {
+                let offset = (address - RAM_START) as usize;
+                if let Some(x) = banked_mut(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset) {
+                    *x = value;
+                    return true;
+                }
+            }
+            MODE_RTC_COMMAND => self.rtc.execute(value),
+            MODE_IR => self.ir_led = value & 0x01 != 0,
+            _ => {}
+        }
+        false
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.rtc.clock.tick(cycles);
+    }
+
+    fn set_clock_source(&mut self, source: ClockSource) {
+        self.rtc.clock = Clock::new(source);
+    }
+}
+
+#[cfg(test)]
+mod huc3_tests {
+    use super::Huc3;
+    use crate::emulator::{
+        cart::{mapper::Mapper, rtc::ClockSource},
+        CLOCK_SPEED,
+    };
+
+    fn setup() -> Huc3 {
+        let mut mbc = Huc3::default();
+        mbc.set_clock_source(ClockSource::Cycles);
+        mbc
+    }
+
+    fn run_seconds(mbc: &mut Huc3, seconds: u64) {
+        for _ in 0..seconds * CLOCK_SPEED / 16 {
+            mbc.tick(16);
+        }
+    }
+
+    fn command(mbc: &mut Huc3, value: u8) -> u8 {
+        mbc.write_rom(0x0000, 0x0B);
+        mbc.write_ram(&mut [], 0xA000, value);
+        mbc.write_rom(0x0000, 0x0C);
+        mbc.read_ram(&[], 0xA000)
+    }
+
+    fn read_time(mbc: &mut Huc3) -> Vec<u8> {
+        command(mbc, 0x60);
+        command(mbc, 0x40);
+        command(mbc, 0x50);
+        (0..7).map(|_| command(mbc, 0x10) & 0x0F).collect()
+    }
+
+    #[test]
+    fn test_ram_needs_write_mode() {
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Huc3::default();
+
+        mbc.write_ram(&mut ram, 0xA000, 69);
+        assert_eq!(ram[0], 0);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_ram(&mut ram, 0xA000, 69);
+        assert_eq!(ram[0x2000], 69);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 69);
+    }
+
+    #[test]
+    fn test_clock_counts_minutes_and_days() {
+        let mut mbc = setup();
+        run_seconds(&mut mbc, 61);
+
+        assert_eq!(read_time(&mut mbc), [1, 0, 0, 0, 0, 0, 0]);
+
+        // set 23:59 on day 0x0102, then let a minute pass
+        let minutes: u16 = 1439;
+        command(&mut mbc, 0x40);
+        command(&mut mbc, 0x50);
+        for nibble in [minutes, minutes >> 4, minutes >> 8, 0x02, 0x01, 0, 0] {
+            command(&mut mbc, 0x30 | (nibble & 0x0F) as u8);
+        }
+        command(&mut mbc, 0x61);
+        run_seconds(&mut mbc, 60);
+
+        assert_eq!(read_time(&mut mbc), [0, 0, 0, 3, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_response_and_semaphore() {
+        let mut mbc = setup();
+
+        assert_eq!(command(&mut mbc, 0x62), 0xE1);
+
+        mbc.write_rom(0x0000, 0x0D);
+        assert_eq!(mbc.read_ram(&[], 0xA000), 0x01);
+    }
+
+    #[test]
+    fn test_ir_register() {
+        let mut mbc = Huc3::default();
+        mbc.write_rom(0x0000, 0x0E);
+
+        mbc.write_ram(&mut [], 0xA000, 0x01);
+
+        assert!(mbc.ir_led());
+        assert_eq!(mbc.read_ram(&[], 0xA000), 0xC0);
+    }
+}
@@ -0,0 +1,111 @@
+use super::rtc::ClockSource;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+pub const RAM_START: u16 = 0xA000;
+
+/// Bank switching hardware on the cart. `Cart` owns the ROM and RAM and hands
+/// them over on each access, the mapper decides which bank the address lands
+/// in and handles writes to its registers in ROM space.
+pub trait Mapper {
+    /// Read from 0x0000-0x7FFF
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8;
+    /// Write to 0x0000-0x7FFF, which only ever reaches mapper registers
+    fn write_rom(&mut self, address: u16, value: u8);
+    /// Read from 0xA000-0xBFFF
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8;
+    /// Write to 0xA000-0xBFFF, returning whether it landed in RAM
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool;
+
+    /// Advances anything on the cart clocked by the CPU
+    fn tick(&mut self, _cycles: u8) {}
+
+    /// Picks what drives the cart's real-time clock, if it has one
+    fn set_clock_source(&mut self, _source: ClockSource) {}
+
+    /// Whether the cart's rumble motor is running
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    /// Whether the cart has a clock that goes in the save file
+    fn has_rtc(&self) -> bool {
+        false
+    }
+
+    /// Clock state to append to the save file, for carts with an RTC
+    fn rtc_footer(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restores clock state from the end of a save file
+    fn load_rtc_footer(&mut self, _footer: &[u8]) {}
+}
+
+/// Byte `offset` into bank `bank` of `data`. Bank numbers past the end wrap
+/// around, the same as the unconnected upper address lines on real carts.
+pub fn banked(data: &[u8], bank: usize, bank_size: usize, offset: usize) -> u8 {
+    if data.is_empty() {
+        // open bus
+        return 0xFF;
+    }
+    data[(bank * bank_size + offset) % data.len()]
+}
+
+pub fn banked_mut(
+    data: &mut [u8],
+    bank: usize,
+    bank_size: usize,
+    offset: usize,
+) -> Option<&mut u8> {
+    if data.is_empty() {
+        return None;
+    }
+    let len = data.len();
+    data.get_mut((bank * bank_size + offset) % len)
+}
+
+/// 32 KiB carts with nothing to switch, optionally with up to 8 KiB of RAM
+#[derive(Default)]
+pub struct RomOnly;
+
+impl Mapper for RomOnly {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        banked(rom, 0, 0, address as usize)
+    }
+
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        banked(ram, 0, 0, (address - RAM_START) as usize)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        match banked_mut(ram, 0, 0, (address - RAM_START) as usize) {
+            Some(x) => {
+                *x = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod mapper_tests {
+    use super::{banked, banked_mut};
+
+    #[test]
+    fn test_banked_wraps_past_end() {
+        let data: Vec<u8> = (0..4).collect();
+
+        assert_eq!(banked(&data, 1, 2, 1), 3);
+        assert_eq!(banked(&data, 3, 2, 0), 2);
+    }
+
+    #[test]
+    fn test_banked_empty_is_open_bus() {
+        assert_eq!(banked(&[], 0, 0x2000, 0), 0xFF);
+        assert!(banked_mut(&mut [], 0, 0x2000, 0).is_none());
+    }
+}
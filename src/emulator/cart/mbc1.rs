@@ -0,0 +1,226 @@
+use super::mapper::{banked, banked_mut, Mapper, RAM_BANK_SIZE, RAM_START, ROM_BANK_SIZE};
+
+/// Offset of the second game's logo in an MBC1M multicart (bank 0x10)
+const MULTICART_LOGO: usize = 0x10 * ROM_BANK_SIZE + LOGO;
+const LOGO: usize = 0x0104;
+const LOGO_LEN: usize = 0x30;
+
+/// MBC1, up to 2 MiB of ROM and 32 KiB of RAM.
+///
+/// The 2-bit register at 0x4000-0x5FFF is shared: it's either bits 5-6 of
+/// the ROM bank or the RAM bank, and in mode 1 it also switches the bank
+/// mapped at 0x0000-0x3FFF.
+#[derive(Default)]
+pub struct Mbc1 {
+    ram_enabled: bool,
+    /// 5-bit register at 0x2000-0x3FFF
+    rom_bank: u8,
+    /// 2-bit register at 0x4000-0x5FFF
+    upper: u8,
+    /// Banking mode select at 0x6000-0x7FFF
+    mode: bool,
+    /// MBC1M multicarts only wire up 4 bits of the ROM bank register, so the
+    /// upper bits select one of four 256 KiB games.
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            rom_bank: 1,
+            multicart: is_multicart(rom),
+            ..Default::default()
+        }
+    }
+
+    fn upper_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn low_bank(&self) -> usize {
+        if self.mode {
+            (self.upper << self.upper_shift()) as usize
+        } else {
+            0
+        }
+    }
+
+    fn high_bank(&self) -> usize {
+        let low_mask = if self.multicart { 0x0F } else { 0x1F };
+        ((self.upper << self.upper_shift()) | (self.rom_bank & low_mask)) as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.mode {
+            self.upper as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => banked(rom, self.low_bank(), ROM_BANK_SIZE, address as usize),
+            _ => banked(
+                rom,
+                self.high_bank(),
+                ROM_BANK_SIZE,
+                (address & 0x3FFF) as usize,
+            ),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // bank 0 can't be selected here, the comparison only sees
+                // these 5 bits so 0x20/0x40/0x60 also end up one higher
+                self.rom_bank = match value & 0x1F {
+                    0 => 1,
+                    bank => bank,
+                }
+            }
+            0x4000..=0x5FFF => self.upper = value & 0x03,
+            _ => self.mode = value & 0x01 != 0,
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        banked(
+            ram,
+            self.ram_bank(),
+            RAM_BANK_SIZE,
+            (address - RAM_START) as usize,
+        )
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        let offset = (address - RAM_START) as usize;
+        match banked_mut(ram, self.ram_bank(), RAM_BANK_SIZE, offset) {
+            Some(x) => {
+                *x = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// MBC1M carts are 1 MiB with a second copy of the Nintendo logo at the start
+/// of bank 0x10, where the menu hands over to the next game.
+fn is_multicart(rom: &[u8]) -> bool {
+    rom.len() == 0x100000
+        && rom[LOGO..LOGO + LOGO_LEN] == rom[MULTICART_LOGO..MULTICART_LOGO + LOGO_LEN]
+}
+
+#[cfg(test)]
+mod mbc1_tests {
+    use super::{Mbc1, LOGO, LOGO_LEN, MULTICART_LOGO};
+    use crate::emulator::cart::mapper::{Mapper, ROM_BANK_SIZE};
+
+    /// Every bank starts with its own number
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_rom_bank_zero_maps_to_one() {
+        let rom = rom(4);
+        let mut mbc = Mbc1::new(&rom);
+
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+
+        mbc.write_rom(0x2000, 3);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 3);
+
+        mbc.write_rom(0x2000, 0);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+    }
+
+    #[test]
+    fn test_bank_number_wraps_to_rom_size() {
+        let rom = rom(4);
+        let mut mbc = Mbc1::new(&rom);
+
+        mbc.write_rom(0x2000, 6);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 2);
+    }
+
+    #[test]
+    fn test_upper_bits_select_large_rom_banks() {
+        let rom = rom(128);
+        let mut mbc = Mbc1::new(&rom);
+
+        // 0x20 can't be reached directly, the low bits read as 0 and become 1
+        mbc.write_rom(0x4000, 1);
+        mbc.write_rom(0x2000, 0);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x21);
+
+        // 0x0000-0x3FFF follows the upper bits only in mode 1
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+        mbc.write_rom(0x6000, 1);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x20);
+    }
+
+    #[test]
+    fn test_ram_enable_and_banking() {
+        let rom = rom(4);
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc1::new(&rom);
+
+        mbc.write_ram(&mut ram, 0xA000, 69);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        assert_eq!(ram[0], 0);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 69);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 69);
+
+        // RAM banking only applies in mode 1
+        mbc.write_rom(0x4000, 2);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 69);
+
+        mbc.write_rom(0x6000, 1);
+        mbc.write_ram(&mut ram, 0xA001, 99);
+        assert_eq!(ram[2 * 0x2000 + 1], 99);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA001), 0xFF);
+    }
+
+    #[test]
+    fn test_multicart_wiring() {
+        let mut rom = rom(64);
+        let logo: Vec<u8> = (0..LOGO_LEN as u8).collect();
+        rom[LOGO..LOGO + LOGO_LEN].copy_from_slice(&logo);
+        rom[MULTICART_LOGO..MULTICART_LOGO + LOGO_LEN].copy_from_slice(&logo);
+        let mut mbc = Mbc1::new(&rom);
+
+        assert!(mbc.multicart);
+
+        // only 4 bits of the ROM bank register are connected
+        mbc.write_rom(0x2000, 0x12);
+        mbc.write_rom(0x4000, 1);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x12);
+
+        mbc.write_rom(0x6000, 1);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x10);
+    }
+}
@@ -0,0 +1,115 @@
+use super::mapper::{banked, Mapper, ROM_BANK_SIZE};
+
+/// 512 half-bytes of RAM built into the MBC2 chip itself
+pub const RAM_SIZE: usize = 0x200;
+
+/// Selects between the two registers in 0x0000-0x3FFF
+const REGISTER_SELECT_BIT: u16 = 0x0100;
+
+/// MBC2, up to 256 KiB of ROM and 512x4 bits of built-in RAM.
+#[derive(Debug)]
+pub struct Mbc2 {
+    ram_enabled: bool,
+    /// 4-bit register
+    rom_bank: u8,
+}
+
+impl Default for Mbc2 {
+    fn default() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Mapper for Mbc2 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => banked(rom, 0, ROM_BANK_SIZE, address as usize),
+            _ => banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (address & 0x3FFF) as usize,
+            ),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        // both registers live in the lower half, told apart by address bit 8
+        match address {
+            0x0000..=0x3FFF if address & REGISTER_SELECT_BIT == 0 => {
+                self.ram_enabled = value & 0x0F == 0x0A
+            }
+            0x0000..=0x3FFF => {
+                self.rom_bank = match value & 0x0F {
+                    0 => 1,
+                    bank => bank,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        // only the low nibble is wired, the rest of 0xA000-0xBFFF echoes it
+        0xF0 | banked(ram, 0, 0, address as usize & (RAM_SIZE - 1))
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match ram.get_mut(address as usize & (RAM_SIZE - 1)) {
+            Some(x) => {
+                *x = value & 0x0F;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod mbc2_tests {
+    use super::{Mbc2, RAM_SIZE};
+    use crate::emulator::cart::mapper::{Mapper, ROM_BANK_SIZE};
+
+    #[test]
+    fn test_address_bit_8_selects_register() {
+        let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+        rom[5 * ROM_BANK_SIZE] = 69;
+        let mut mbc = Mbc2::default();
+
+        // bit 8 clear, RAM enable rather than a bank switch
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0);
+
+        mbc.write_rom(0x2100, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 69);
+
+        mbc.write_rom(0x0100, 0x00);
+        assert_eq!(mbc.rom_bank, 1);
+    }
+
+    #[test]
+    fn test_half_byte_ram_echoes() {
+        let mut ram = vec![0; RAM_SIZE];
+        let mut mbc = Mbc2::default();
+
+        mbc.write_ram(&mut ram, 0xA000, 0xAB);
+        assert_eq!(ram[0], 0);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA001, 0xAB);
+
+        assert_eq!(ram[1], 0x0B);
+        assert_eq!(mbc.read_ram(&ram, 0xA001), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xA201), 0xFB);
+        assert_eq!(mbc.read_ram(&ram, 0xBE01), 0xFB);
+    }
+}
@@ -0,0 +1,478 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{
+    mapper::{banked, banked_mut, Mapper, RAM_BANK_SIZE, RAM_START, ROM_BANK_SIZE},
+    rtc::{Clock, ClockSource},
+};
+
+const DAYS_HIGH_BIT: u8 = 0x01;
+const HALT_BIT: u8 = 0x40;
+const CARRY_BIT: u8 = 0x80;
+
+/// Save file footer shared with BGB/VBA-M: live then latched registers as
+/// five little-endian u32s each, followed by a u64 unix timestamp
+pub const RTC_FOOTER_SIZE: usize = 48;
+/// Older variant with a u32 timestamp
+const RTC_FOOTER_SIZE_32: usize = 44;
+
+/// MBC3 clock registers, selected by writing 0x08-0x0C to 0x4000-0x5FFF.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    /// 9-bit day counter
+    pub days: u16,
+    pub halt: bool,
+    /// Set when the day counter overflows, stays set until cleared
+    pub carry: bool,
+}
+
+impl RtcRegisters {
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            0x0C => {
+                let mut value = (self.days >> 8) as u8 & DAYS_HIGH_BIT;
+                if self.halt {
+                    value |= HALT_BIT;
+                }
+                if self.carry {
+                    value |= CARRY_BIT;
+                }
+                value
+            }
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | ((value & DAYS_HIGH_BIT) as u16) << 8;
+                self.halt = value & HALT_BIT != 0;
+                self.carry = value & CARRY_BIT != 0;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn advance(&mut self, mut seconds: u64) {
+        if self.halt {
+            return;
+        }
+
+        // Out of range values count up to their register width and wrap
+        // without carrying, step through those one second at a time.
+        while seconds > 0 && !self.in_range() {
+            self.tick_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let total = seconds
+            + self.seconds as u64
+            + 60 * (self.minutes as u64 + 60 * (self.hours as u64 + 24 * self.days as u64));
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+
+        let days = total / 86400;
+        if days > 0x1FF {
+            self.carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.carry = true;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Rtc {
+    clock: Clock,
+    live: RtcRegisters,
+    /// Copy the game reads from, refreshed by the latch sequence
+    latched: RtcRegisters,
+    /// Last value written to 0x6000-0x7FFF, latching happens on 0 then 1
+    latch_write: Option<u8>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+impl Rtc {
+    /// Catches the live registers up with the clock
+    fn update(&mut self) {
+        let seconds = self.clock.elapsed_seconds();
+        self.live.advance(seconds);
+    }
+
+    fn latch(&mut self, value: u8) {
+        if self.latch_write == Some(0x00) && value == 0x01 {
+            self.update();
+            self.latched = self.live;
+        }
+        self.latch_write = Some(value);
+    }
+
+    fn footer(&mut self) -> Vec<u8> {
+        self.update();
+        let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+        for registers in [self.live, self.latched] {
+            for register in 0x08..=0x0C {
+                footer.extend_from_slice(&(registers.read(register) as u32).to_le_bytes());
+            }
+        }
+        footer.extend_from_slice(&unix_now().to_le_bytes());
+        footer
+    }
+
+    fn load_footer(&mut self, footer: &[u8]) {
+        let timestamp = match footer.len() {
+            RTC_FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            RTC_FOOTER_SIZE_32 => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            _ => return,
+        };
+        let word = |i: usize| footer[4 * i];
+        for register in 0x08..=0x0C {
+            let i = (register - 0x08) as usize;
+            self.live.write(register, word(i));
+            self.latched.write(register, word(5 + i));
+        }
+
+        // the clock kept running while the emulator was closed
+        if self.clock.source() == ClockSource::WallTime {
+            self.live.advance(unix_now().saturating_sub(timestamp));
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        self.update();
+        if register == 0x08 {
+            self.clock.reset_subsecond();
+        }
+        self.live.write(register, value);
+    }
+}
+
+/// MBC3, up to 2 MiB of ROM, 32 KiB of RAM and an optional real-time clock.
+#[derive(Debug)]
+pub struct Mbc3 {
+    /// Enables both RAM and the clock registers
+    ram_enabled: bool,
+    /// 7-bit register at 0x2000-0x3FFF
+    rom_bank: u8,
+    /// RAM bank (0x00-0x03) or clock register (0x08-0x0C)
+    ram_select: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub fn new(has_rtc: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            rtc: has_rtc.then(Rtc::default),
+        }
+    }
+}
+
+impl Mapper for Mbc3 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => banked(rom, 0, ROM_BANK_SIZE, address as usize),
+            _ => banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (address & 0x3FFF) as usize,
+            ),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0x7F {
+                    0 => 1,
+                    bank => bank,
+                }
+            }
+            0x4000..=0x5FFF => self.ram_select = value,
+            _ => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.latch(value);
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match (self.ram_select, &self.rtc) {
+            (0x00..=0x03, _) => banked(
+                ram,
+                self.ram_select as usize,
+                RAM_BANK_SIZE,
+                (address - RAM_START) as usize,
+            ),
+            (0x08..=0x0C, Some(rtc)) => rtc.latched.read(self.ram_select),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match (self.ram_select, &mut self.rtc) {
+            (0x00..=0x03, _) => {
+                let offset = (address - RAM_START) as usize;
+                if let Some(x) = banked_mut(ram, self.ram_select as usize, RAM_BANK_SIZE, offset) {
+                    *x = value;
+                    return true;
+                }
+            }
+            (0x08..=0x0C, Some(rtc)) => rtc.write(self.ram_select, value),
+            _ => {}
+        }
+        false
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.clock.tick(cycles);
+        }
+    }
+
+    fn set_clock_source(&mut self, source: ClockSource) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.clock = Clock::new(source);
+        }
+    }
+
+    fn has_rtc(&self) -> bool {
+        self.rtc.is_some()
+    }
+
+    fn rtc_footer(&mut self) -> Option<Vec<u8>> {
+        self.rtc.as_mut().map(Rtc::footer)
+    }
+
+    fn load_rtc_footer(&mut self, footer: &[u8]) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_footer(footer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod mbc3_tests {
+    use super::{unix_now, Mbc3, RtcRegisters, RTC_FOOTER_SIZE};
+    use crate::emulator::{
+        cart::{mapper::Mapper, rtc::ClockSource},
+        CLOCK_SPEED,
+    };
+
+    fn setup() -> Mbc3 {
+        let mut mbc = Mbc3::new(true);
+        mbc.set_clock_source(ClockSource::Cycles);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc
+    }
+
+    fn run_seconds(mbc: &mut Mbc3, seconds: u64) {
+        for _ in 0..seconds * CLOCK_SPEED / 16 {
+            mbc.tick(16);
+        }
+    }
+
+    fn latch(mbc: &mut Mbc3) {
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+    }
+
+    fn read_register(mbc: &mut Mbc3, register: u8) -> u8 {
+        mbc.write_rom(0x4000, register);
+        mbc.read_ram(&[], 0xA000)
+    }
+
+    #[test]
+    fn test_rom_banking() {
+        let mut rom = vec![0; 128 * 0x4000];
+        rom[0x7F * 0x4000] = 69;
+        let mut mbc = Mbc3::new(false);
+
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 69);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.rom_bank, 1);
+    }
+
+    #[test]
+    fn test_ram_banking() {
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = setup();
+
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(&mut ram, 0xA010, 69);
+
+        assert_eq!(ram[3 * 0x2000 + 0x10], 69);
+        assert_eq!(mbc.read_ram(&ram, 0xA010), 69);
+    }
+
+    #[test]
+    fn test_latched_registers_only_change_on_latch() {
+        let mut mbc = setup();
+        run_seconds(&mut mbc, 2);
+        assert_eq!(read_register(&mut mbc, 0x08), 0);
+
+        latch(&mut mbc);
+        assert_eq!(read_register(&mut mbc, 0x08), 2);
+
+        // 1 alone doesn't latch, it has to follow a 0
+        run_seconds(&mut mbc, 1);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(read_register(&mut mbc, 0x08), 2);
+    }
+
+    #[test]
+    fn test_halt_stops_the_clock() {
+        let mut mbc = setup();
+        mbc.write_rom(0x4000, 0x0C);
+        mbc.write_ram(&mut [], 0xA000, 0x40);
+
+        run_seconds(&mut mbc, 3);
+        latch(&mut mbc);
+
+        assert_eq!(read_register(&mut mbc, 0x08), 0);
+        assert_eq!(read_register(&mut mbc, 0x0C), 0x40);
+    }
+
+    #[test]
+    fn test_advance_carries_into_days() {
+        let mut registers = RtcRegisters {
+            seconds: 59,
+            minutes: 59,
+            hours: 23,
+            days: 0x1FF,
+            ..Default::default()
+        };
+
+        registers.advance(1);
+
+        assert_eq!(
+            registers,
+            RtcRegisters {
+                carry: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(registers.read(0x0C), 0x80);
+    }
+
+    #[test]
+    fn test_advance_out_of_range_wraps_without_carry() {
+        let mut registers = RtcRegisters {
+            seconds: 62,
+            ..Default::default()
+        };
+
+        registers.advance(2);
+        assert_eq!(registers.seconds, 0);
+        assert_eq!(registers.minutes, 0);
+
+        registers.advance(61);
+        assert_eq!(registers.seconds, 1);
+        assert_eq!(registers.minutes, 1);
+    }
+
+    #[test]
+    fn test_footer_round_trip() {
+        let mut mbc = setup();
+        run_seconds(&mut mbc, 5);
+        latch(&mut mbc);
+        run_seconds(&mut mbc, 2);
+
+        let footer = mbc.rtc_footer().unwrap();
+        assert_eq!(footer.len(), RTC_FOOTER_SIZE);
+        assert_eq!(footer[0], 7);
+        assert_eq!(footer[20], 5);
+
+        let mut loaded = setup();
+        loaded.load_rtc_footer(&footer);
+        assert_eq!(read_register(&mut loaded, 0x08), 5);
+        latch(&mut loaded);
+        assert_eq!(read_register(&mut loaded, 0x08), 7);
+    }
+
+    #[test]
+    fn test_footer_catches_up_with_wall_time() {
+        let mut footer = vec![0; RTC_FOOTER_SIZE];
+        footer[40..].copy_from_slice(&(unix_now() - 90).to_le_bytes());
+        let mut mbc = Mbc3::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.load_rtc_footer(&footer);
+        latch(&mut mbc);
+
+        assert_eq!(read_register(&mut mbc, 0x09), 1);
+        assert!(read_register(&mut mbc, 0x08) >= 30);
+    }
+
+    #[test]
+    fn test_day_counter_high_bit() {
+        let mut registers = RtcRegisters::default();
+        registers.write(0x0B, 0xFF);
+        registers.write(0x0C, 0x01);
+
+        assert_eq!(registers.days, 0x1FF);
+        assert_eq!(registers.read(0x0B), 0xFF);
+        assert_eq!(registers.read(0x0C), 0x01);
+    }
+}
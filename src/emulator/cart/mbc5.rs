@@ -0,0 +1,151 @@
+use super::mapper::{banked, banked_mut, Mapper, RAM_BANK_SIZE, RAM_START, ROM_BANK_SIZE};
+
+/// On rumble carts bit 3 of the RAM bank register drives the motor instead
+const RUMBLE_BIT: u8 = 0x08;
+
+/// MBC5, up to 8 MiB of ROM and 128 KiB of RAM. Unlike the earlier MBCs
+/// bank 0 can be mapped into the switchable window.
+#[derive(Debug, Default)]
+pub struct Mbc5 {
+    ram_enabled: bool,
+    /// 9-bit, low byte at 0x2000-0x2FFF and bit 8 at 0x3000-0x3FFF
+    rom_bank: u16,
+    ram_bank: u8,
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn new(has_rumble: bool) -> Self {
+        Self {
+            rom_bank: 1,
+            has_rumble,
+            ..Default::default()
+        }
+    }
+}
+
+impl Mapper for Mbc5 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => banked(rom, 0, ROM_BANK_SIZE, address as usize),
+            _ => banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (address & 0x3FFF) as usize,
+            ),
+        }
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value & 0x01) as u16) << 8
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = value & RUMBLE_BIT != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        banked(
+            ram,
+            self.ram_bank as usize,
+            RAM_BANK_SIZE,
+            (address - RAM_START) as usize,
+        )
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        let offset = (address - RAM_START) as usize;
+        match banked_mut(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset) {
+            Some(x) => {
+                *x = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+#[cfg(test)]
+mod mbc5_tests {
+    use super::Mbc5;
+    use crate::emulator::cart::mapper::{Mapper, ROM_BANK_SIZE};
+
+    #[test]
+    fn test_nine_bit_rom_bank() {
+        let mut rom = vec![0; 512 * ROM_BANK_SIZE];
+        rom[0x1FF * ROM_BANK_SIZE] = 69;
+        rom[0x100 * ROM_BANK_SIZE] = 99;
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_rom(0x2000, 0xFF);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 69);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 99);
+    }
+
+    #[test]
+    fn test_bank_zero_in_switchable_window() {
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        rom[0] = 69;
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_rom(0x2000, 0x00);
+
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 69);
+    }
+
+    #[test]
+    fn test_sixteen_ram_banks() {
+        let mut ram = vec![0; 16 * 0x2000];
+        let mut mbc = Mbc5::new(false);
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(&mut ram, 0xA000, 69);
+
+        assert_eq!(ram[15 * 0x2000], 69);
+        assert!(!mbc.rumble());
+    }
+
+    #[test]
+    fn test_rumble_bit() {
+        let mut ram = vec![0; 16 * 0x2000];
+        let mut mbc = Mbc5::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0x0B);
+        assert!(mbc.rumble());
+
+        // the motor bit isn't part of the bank number
+        mbc.write_ram(&mut ram, 0xA000, 69);
+        assert_eq!(ram[3 * 0x2000], 69);
+
+        mbc.write_rom(0x4000, 0x03);
+        assert!(!mbc.rumble());
+    }
+}
@@ -0,0 +1,191 @@
+use super::mapper::{banked, banked_mut, Mapper, RAM_BANK_SIZE, RAM_START, ROM_BANK_SIZE};
+
+/// Written to 0x0000-0x1FFF alongside the RAM enable, locks the outer bank
+const MAP_BIT: u8 = 0x40;
+/// Written to 0x4000-0x5FFF, freezes the banking mode
+const MODE_LOCK_BIT: u8 = 0x40;
+
+/// MMM01 multicarts. The cart boots in an unmapped state with the menu in the
+/// last 32 KiB of ROM, which picks a game by writing its outer bank bits and
+/// then maps it in. From then on the game sees an MBC1 limited to its own
+/// slice of the ROM.
+#[derive(Debug, Default)]
+pub struct Mmm01 {
+    mapped: bool,
+    ram_enabled: bool,
+    /// 9 bits, 0-4 are the game's own bank register, 5-8 are the outer bank
+    rom_bank: u16,
+    /// 4 bits, 0-1 are the game's own register, 2-3 are the outer bank
+    ram_bank: u8,
+    /// ROM bank bits 1-4 the game isn't allowed to change
+    rom_mask: u8,
+    mode: bool,
+    mode_locked: bool,
+}
+
+impl Mmm01 {
+    /// The low bank bits the game's writes to 0x2000-0x3FFF can change
+    fn writable_bank_bits(&self) -> u16 {
+        0x1F & !((self.rom_mask as u16) << 1)
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        if !self.mapped {
+            // the menu, the last two banks of the ROM
+            return match address {
+                0x0000..=0x3FFF => 0x1FE,
+                _ => 0x1FF,
+            };
+        }
+        match address {
+            0x0000..=0x3FFF => (self.rom_bank & !self.writable_bank_bits()) as usize,
+            _ => self.rom_bank as usize,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.mode {
+            self.ram_bank as usize
+        } else {
+            (self.ram_bank & 0x0C) as usize
+        }
+    }
+}
+
+impl Mapper for Mmm01 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        banked(
+            rom,
+            self.rom_bank(address),
+            ROM_BANK_SIZE,
+            (address & 0x3FFF) as usize,
+        )
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+                if value & MAP_BIT != 0 {
+                    self.mapped = true;
+                }
+            }
+            0x2000..=0x3FFF => {
+                // the menu can set every bit, the game only the unmasked ones
+                let writable = if self.mapped {
+                    self.writable_bank_bits()
+                } else {
+                    0x7F
+                };
+                self.rom_bank = (self.rom_bank & !writable) | (value as u16 & writable);
+                // MBC1's 0 -> 1 quirk, only looking at the game's bits
+                if self.rom_bank & self.writable_bank_bits() == 0 {
+                    self.rom_bank |= 1;
+                }
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank = (self.ram_bank & 0x0C) | (value & 0x03);
+                if !self.mapped {
+                    self.ram_bank = (self.ram_bank & 0x03) | (value & 0x0C);
+                    self.rom_bank = (self.rom_bank & 0x7F) | ((value as u16 & 0x30) << 3);
+                    self.mode_locked = value & MODE_LOCK_BIT != 0;
+                }
+            }
+            _ => {
+                if !self.mode_locked {
+                    self.mode = value & 0x01 != 0;
+                }
+                if !self.mapped {
+                    self.rom_mask = (value >> 2) & 0x0F;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        banked(
+            ram,
+            self.ram_bank(),
+            RAM_BANK_SIZE,
+            (address - RAM_START) as usize,
+        )
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        let offset = (address - RAM_START) as usize;
+        match banked_mut(ram, self.ram_bank(), RAM_BANK_SIZE, offset) {
+            Some(x) => {
+                *x = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod mmm01_tests {
+    use super::Mmm01;
+    use crate::emulator::cart::mapper::{Mapper, ROM_BANK_SIZE};
+
+    /// 512 KiB with every bank starting with its own number
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 32 * ROM_BANK_SIZE];
+        for bank in 0..32 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn test_boots_into_menu() {
+        let rom = rom();
+        let mbc = Mmm01::default();
+
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 30);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 31);
+    }
+
+    #[test]
+    fn test_menu_maps_game() {
+        let rom = rom();
+        let mut mbc = Mmm01::default();
+
+        // game at banks 8-15, bank bits 3-4 come from the menu
+        mbc.write_rom(0x6000, 0x0C << 2);
+        mbc.write_rom(0x2000, 0x08);
+        mbc.write_rom(0x0000, 0x40);
+
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 8);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 9);
+
+        // the game's own switches stay inside its slice
+        mbc.write_rom(0x2000, 0x03);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 11);
+        mbc.write_rom(0x2000, 0x17);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 15);
+
+        // and the mask can't be changed once mapped
+        mbc.write_rom(0x6000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 8);
+    }
+
+    #[test]
+    fn test_outer_ram_bank() {
+        let mut ram = vec![0; 0x20000];
+        let mut mbc = Mmm01::default();
+
+        mbc.write_rom(0x4000, 0x04);
+        mbc.write_rom(0x0000, 0x4A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(&mut ram, 0xA000, 69);
+
+        assert_eq!(ram[4 * 0x2000], 69);
+    }
+}
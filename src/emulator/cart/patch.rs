@@ -0,0 +1,476 @@
+use core::fmt;
+use std::error::Error;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// UPS and BPS both end with source, target and patch CRC32s
+const FOOTER_SIZE: usize = 12;
+/// Largest ROM any header can describe, bigger targets mean a bad patch
+const MAX_TARGET_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// Not an IPS, UPS or BPS patch
+    UnknownFormat,
+    /// Ends in the middle of a record or points outside the ROM
+    Malformed,
+    /// The patch was made against a different ROM
+    SourceChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    TargetChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    /// The patch file itself is corrupt
+    PatchChecksum {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(f, "Patch isn't IPS, UPS or BPS"),
+            Self::Malformed => write!(f, "Patch is truncated or malformed"),
+            Self::SourceChecksum { expected, actual } => write!(
+                f,
+                "Patch is for a different ROM, expected CRC32 {:08x} but got {:08x}",
+                expected, actual
+            ),
+            Self::TargetChecksum { expected, actual } => write!(
+                f,
+                "Patched ROM CRC32 mismatch, expected {:08x} but got {:08x}",
+                expected, actual
+            ),
+            Self::PatchChecksum { expected, actual } => write!(
+                f,
+                "Patch is corrupt, expected CRC32 {:08x} but got {:08x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+/// Applies an IPS, UPS or BPS patch to `rom`, telling them apart by their
+/// magic numbers
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+/// Reads through a patch, every read past the end is `Malformed`
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(len).ok_or(PatchError::Malformed)?;
+        let bytes = self.data.get(self.pos..end).ok_or(PatchError::Malformed)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Big-endian, as IPS uses
+    fn uint_be(&mut self, len: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |acc, &byte| acc << 8 | byte as usize))
+    }
+
+    /// Variable-length number shared by UPS and BPS, 7 bits per byte with the
+    /// top bit marking the last one
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.u8()?;
+            value = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|x| x.checked_add(value))
+                .ok_or(PatchError::Malformed)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::Malformed)?;
+            value = value.checked_add(shift).ok_or(PatchError::Malformed)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = offset.iter().fold(0, |acc, &byte| acc << 8 | byte as usize);
+
+        let size = reader.uint_be(2)?;
+        // a zero size marks a run of one repeated byte
+        let (size, data) = match size {
+            0 => {
+                let size = reader.uint_be(2)?;
+                (size, vec![reader.u8()?; size])
+            }
+            size => (size, reader.bytes(size)?.to_vec()),
+        };
+
+        if offset + size > MAX_TARGET_SIZE {
+            return Err(PatchError::Malformed);
+        }
+        if out.len() < offset + size {
+            out.resize(offset + size, 0);
+        }
+        out[offset..offset + size].copy_from_slice(&data);
+    }
+
+    // optional truncation extension
+    if let Ok(len) = reader.uint_be(3) {
+        out.truncate(len);
+    }
+    Ok(out)
+}
+
+/// Checks the CRC32 footer shared by UPS and BPS, returning the expected
+/// target CRC32 and the patch without its footer
+fn check_footer<'a>(rom: &[u8], patch: &'a [u8]) -> Result<(u32, &'a [u8]), PatchError> {
+    let body_len = patch
+        .len()
+        .checked_sub(FOOTER_SIZE)
+        .ok_or(PatchError::Malformed)?;
+    let crc = |i: usize| {
+        let start = body_len + 4 * i;
+        u32::from_le_bytes(patch[start..start + 4].try_into().unwrap())
+    };
+
+    let actual = crc32fast::hash(&patch[..patch.len() - 4]);
+    if crc(2) != actual {
+        return Err(PatchError::PatchChecksum {
+            expected: crc(2),
+            actual,
+        });
+    }
+
+    let actual = crc32fast::hash(rom);
+    if crc(0) != actual {
+        return Err(PatchError::SourceChecksum {
+            expected: crc(0),
+            actual,
+        });
+    }
+
+    Ok((crc(1), &patch[..body_len]))
+}
+
+fn check_target(out: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32fast::hash(out);
+    if expected != actual {
+        return Err(PatchError::TargetChecksum { expected, actual });
+    }
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, body) = check_footer(rom, patch)?;
+    let mut reader = Reader::new(body, UPS_MAGIC.len());
+
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Malformed);
+    }
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+
+    // hunks of bytes XORed with the source, each ending in a 0
+    let mut pos: usize = 0;
+    while reader.pos < body.len() {
+        pos = pos
+            .checked_add(reader.varint()?)
+            .ok_or(PatchError::Malformed)?;
+        loop {
+            let byte = reader.u8()?;
+            if byte == 0 {
+                pos += 1;
+                break;
+            }
+            let x = out.get_mut(pos).ok_or(PatchError::Malformed)?;
+            *x ^= byte;
+            pos += 1;
+        }
+    }
+
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+/// Moves `pos` by a BPS relative offset, sign in the low bit
+fn relative(pos: usize, offset: usize) -> Result<usize, PatchError> {
+    let delta = offset >> 1;
+    if offset & 1 != 0 {
+        pos.checked_sub(delta)
+    } else {
+        pos.checked_add(delta)
+    }
+    .ok_or(PatchError::Malformed)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, body) = check_footer(rom, patch)?;
+    let mut reader = Reader::new(body, BPS_MAGIC.len());
+
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Malformed);
+    }
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_pos = 0;
+    let mut target_pos = 0;
+    while reader.pos < body.len() {
+        let action = reader.varint()?;
+        let len = (action >> 2) + 1;
+        // no action may write past the size the header gave
+        if len > target_size - out.len() {
+            return Err(PatchError::Malformed);
+        }
+        match action & 0x03 {
+            // source read, from the same position in the ROM
+            0 => {
+                let start = out.len();
+                let data = rom.get(start..start + len).ok_or(PatchError::Malformed)?;
+                out.extend_from_slice(data);
+            }
+            // target read, straight out of the patch
+            1 => out.extend_from_slice(reader.bytes(len)?),
+            // source copy, from anywhere in the ROM
+            2 => {
+                source_pos = relative(source_pos, reader.varint()?)?;
+                let end = source_pos.checked_add(len).ok_or(PatchError::Malformed)?;
+                let data = rom.get(source_pos..end).ok_or(PatchError::Malformed)?;
+                out.extend_from_slice(data);
+                source_pos = end;
+            }
+            // target copy, from earlier output, byte by byte since the
+            // ranges can overlap
+            _ => {
+                target_pos = relative(target_pos, reader.varint()?)?;
+                for _ in 0..len {
+                    let byte = *out.get(target_pos).ok_or(PatchError::Malformed)?;
+                    out.push(byte);
+                    target_pos += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size {
+        return Err(PatchError::Malformed);
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod patch_tests {
+    use super::{apply, PatchError};
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut out = vec![];
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte | 0x80);
+                return out;
+            }
+            out.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32fast::hash(source).to_le_bytes());
+        patch.extend(crc32fast::hash(target).to_le_bytes());
+        patch.extend(crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    fn ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        // one hunk per run of differing bytes, the terminating 0 covers
+        // the byte after the run
+        let xor = |i: usize| target[i] ^ source.get(i).copied().unwrap_or(0);
+        let mut pos = 0;
+        let mut i = 0;
+        while i < target.len() {
+            if xor(i) == 0 {
+                i += 1;
+                continue;
+            }
+            patch.extend(varint(i - pos));
+            while i < target.len() && xor(i) != 0 {
+                patch.push(xor(i));
+                i += 1;
+            }
+            patch.push(0);
+            i += 1;
+            pos = i;
+        }
+        with_footer(patch, source, target)
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = vec![0; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        // RLE run past the end grows the ROM
+        patch.extend([0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend(b"EOF");
+
+        assert_eq!(
+            apply(&rom, &patch).unwrap(),
+            [0, 0, 0xAA, 0xBB, 0, 0, 0, 0xCC, 0xCC, 0xCC]
+        );
+    }
+
+    #[test]
+    fn test_ips_truncated() {
+        let patch = b"PATCH\x00\x00\x02\x00\x02\xAA".to_vec();
+
+        assert_eq!(apply(&[0; 8], &patch), Err(PatchError::Malformed));
+    }
+
+    #[test]
+    fn test_ups() {
+        let source = [1, 2, 3, 4, 5, 6];
+        let target = [1, 9, 3, 4, 5, 6, 7, 8];
+
+        assert_eq!(apply(&source, &ups(&source, &target)).unwrap(), target);
+    }
+
+    #[test]
+    fn test_ups_wrong_source() {
+        let source = [1, 2, 3, 4];
+        let patch = ups(&source, &[1, 2, 3, 5]);
+
+        assert!(matches!(
+            apply(&[1, 2, 3, 3], &patch),
+            Err(PatchError::SourceChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_patch() {
+        let source = [1, 2, 3, 4];
+        let mut patch = ups(&source, &[1, 2, 3, 5]);
+        patch[5] ^= 0xFF;
+
+        assert!(matches!(
+            apply(&source, &patch),
+            Err(PatchError::PatchChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"hello world".to_vec();
+        let target = b"hello hello world!!!!".to_vec();
+
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        // source read "hello "
+        patch.extend(varint((6 - 1) << 2));
+        // source copy "hello world" from offset 0
+        patch.extend(varint((11 - 1) << 2 | 2));
+        patch.extend(varint(0));
+        // target read "!"
+        patch.extend(varint(1));
+        patch.push(b'!');
+        // target copy "!!!" from the "!" just written, overlapping
+        patch.extend(varint((3 - 1) << 2 | 3));
+        patch.extend(varint(17 << 1));
+
+        assert_eq!(
+            apply(&source, &with_footer(patch, &source, &target)).unwrap(),
+            target
+        );
+    }
+
+    #[test]
+    fn test_bps_target_copy_past_target_size() {
+        let source = b"ab".to_vec();
+        let target = b"abb".to_vec();
+
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        // target read "a", then a copy far longer than the target
+        patch.extend(varint(1));
+        patch.push(b'a');
+        patch.extend(varint((1000 - 1) << 2 | 3));
+        patch.extend(varint(0));
+
+        assert_eq!(
+            apply(&source, &with_footer(patch, &source, &target)),
+            Err(PatchError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_bps_huge_metadata_size() {
+        let source = b"ab".to_vec();
+
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(source.len()));
+        patch.extend(varint(usize::MAX));
+
+        assert_eq!(
+            apply(&source, &with_footer(patch, &source, &source)),
+            Err(PatchError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        assert_eq!(apply(&[0; 4], b"NOPE"), Err(PatchError::UnknownFormat));
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::{Duration, SystemTime};
+
+use crate::emulator::CLOCK_SPEED;
+
+/// What drives a cart's real-time clock forward
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Host time, so the clock keeps running while the emulator is closed
+    #[default]
+    WallTime,
+    /// Emulated T-cycles, deterministic for tests and replays
+    Cycles,
+}
+
+/// Hands out whole elapsed seconds from the selected source, carrying the
+/// remainder over to the next call.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    source: ClockSource,
+    /// Cycles counted towards the next second
+    cycles: u64,
+    /// Host time up to which seconds have been handed out
+    last: SystemTime,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(ClockSource::default())
+    }
+}
+
+impl Clock {
+    pub fn new(source: ClockSource) -> Self {
+        Self {
+            source,
+            cycles: 0,
+            last: SystemTime::now(),
+        }
+    }
+
+    pub fn source(&self) -> ClockSource {
+        self.source
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        if self.source == ClockSource::Cycles {
+            self.cycles += cycles as u64;
+        }
+    }
+
+    pub fn elapsed_seconds(&mut self) -> u64 {
+        match self.source {
+            ClockSource::Cycles => {
+                let seconds = self.cycles / CLOCK_SPEED;
+                self.cycles %= CLOCK_SPEED;
+                seconds
+            }
+            ClockSource::WallTime => {
+                // a clock moved backwards just means no time has passed
+                let seconds = SystemTime::now()
+                    .duration_since(self.last)
+                    .map_or(0, |elapsed| elapsed.as_secs());
+                self.last += Duration::from_secs(seconds);
+                seconds
+            }
+        }
+    }
+
+    /// Drops the partial second counted so far, writing the seconds register
+    /// does this on hardware.
+    pub fn reset_subsecond(&mut self) {
+        self.cycles = 0;
+        self.last = SystemTime::now();
+    }
+}
+
+#[cfg(test)]
+mod rtc_tests {
+    use super::{Clock, ClockSource};
+    use crate::emulator::CLOCK_SPEED;
+
+    #[test]
+    fn test_cycles_carry_over() {
+        let mut clock = Clock::new(ClockSource::Cycles);
+        for _ in 0..(CLOCK_SPEED / 4 * 3 / 2) {
+            clock.tick(4);
+        }
+
+        assert_eq!(clock.elapsed_seconds(), 1);
+        assert_eq!(clock.elapsed_seconds(), 0);
+
+        for _ in 0..(CLOCK_SPEED / 4 / 2) {
+            clock.tick(4);
+        }
+        assert_eq!(clock.elapsed_seconds(), 1);
+    }
+
+    #[test]
+    fn test_wall_time_ignores_cycles() {
+        let mut clock = Clock::new(ClockSource::WallTime);
+        for _ in 0..(CLOCK_SPEED / 4 * 2) {
+            clock.tick(4);
+        }
+
+        assert_eq!(clock.elapsed_seconds(), 0);
+    }
+}
@@ -2,13 +2,36 @@ pub mod cpu_registers;
 
 use self::cpu_registers::CPURegisters;
 use super::{
-    instructions::{self, Instruction},
-    memory::Memory,
+    bus::Bus,
+    instructions::{self, ExecutionError, Instruction, OpKind},
+    interrupts::{Interrupt, DISPATCH_CYCLES},
+    timer::DIV_ADDRESS,
 };
 
+/// T-cycles spent per step while halted or stopped (one M-cycle)
+const IDLE_CYCLES: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    Running,
+    /// Entered by HALT, left once any enabled interrupt is requested
+    Halted,
+    /// Entered by STOP, left on joypad input
+    Stopped,
+}
+
 pub struct CPU {
     registers: CPURegisters,
     instructions: Vec<Instruction>,
+    cb_instructions: Vec<Instruction>,
+    /// Interrupt master enable
+    ime: bool,
+    /// Set by EI, IME is only enabled after the following instruction
+    ime_scheduled: bool,
+    state: CpuState,
+    /// Set when HALT is skipped with IME off and an interrupt already pending,
+    /// the next opcode is then read without PC being incremented.
+    halt_bug: bool,
 }
 
 impl Default for CPU {
@@ -16,6 +39,11 @@ impl Default for CPU {
         Self {
             registers: CPURegisters::new(),
             instructions: vec![],
+            cb_instructions: vec![],
+            ime: false,
+            ime_scheduled: false,
+            state: CpuState::Running,
+            halt_bug: false,
         }
     }
 }
@@ -23,13 +51,358 @@ impl Default for CPU {
 impl CPU {
     pub fn load_instructions(&mut self) {
         self.instructions = instructions::fetch_instructions();
+        self.cb_instructions = instructions::fetch_cb_instructions();
     }
-    pub fn execute(&mut self, memory: &mut Memory) {
-        let opcode = memory.read_u8(self.registers.pc);
-        instructions::execute_instruction(
-            &self.instructions[opcode as usize],
+    /// Runs a single instruction or interrupt dispatch, returning the T-cycles
+    /// it took.
+    pub fn execute(&mut self, memory: &mut Bus) -> Result<u8, ExecutionError> {
+        match self.state {
+            CpuState::Running => {}
+            CpuState::Halted => {
+                if memory.interrupts.pending() == 0 {
+                    return Ok(IDLE_CYCLES);
+                }
+                self.state = CpuState::Running;
+            }
+            CpuState::Stopped => {
+                if memory.interrupts.flag & Interrupt::Joypad.mask() == 0 {
+                    return Ok(IDLE_CYCLES);
+                }
+                self.state = CpuState::Running;
+                memory.stopped = false;
+            }
+        }
+
+        if let Some(cycles) = self.service_interrupt(memory) {
+            return Ok(cycles);
+        }
+
+        if self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
+
+        // The HALT bug skips the PC increment after fetching the opcode, so
+        // the opcode byte is fetched again as the next byte
+        let pc = self.registers.pc;
+        let operands = if std::mem::take(&mut self.halt_bug) {
+            pc
+        } else {
+            pc.wrapping_add(1)
+        };
+
+        let opcode = memory.read_u8(pc);
+        let instruction = if opcode == 0xCB {
+            &self.cb_instructions[memory.read_u8(operands) as usize]
+        } else {
+            &self.instructions[opcode as usize]
+        };
+        let kind = instruction.data.kind;
+
+        let cycles = instructions::execute_instruction_at(
+            instruction,
             &mut self.registers,
             memory,
-        );
+            operands,
+        )?;
+
+        match kind {
+            OpKind::Di => self.ime = false,
+            OpKind::Ei => self.ime_scheduled = true,
+            OpKind::Reti => self.ime = true,
+            OpKind::Halt => self.halt(memory),
+            OpKind::Stop => self.stop(memory),
+            _ => {}
+        }
+
+        Ok(cycles)
+    }
+
+    pub fn state(&self) -> CpuState {
+        self.state
+    }
+
+    pub fn registers(&self) -> &CPURegisters {
+        &self.registers
+    }
+
+    fn halt(&mut self, memory: &Bus) {
+        if !self.ime && memory.interrupts.pending() != 0 {
+            self.halt_bug = true;
+        } else {
+            self.state = CpuState::Halted;
+        }
+    }
+
+    fn stop(&mut self, memory: &mut Bus) {
+        // any write to DIV resets it
+        memory.write_u8(DIV_ADDRESS, 0);
+        // only a press from here on wakes it, not one still flagged from
+        // before
+        memory.interrupts.flag &= !Interrupt::Joypad.mask();
+        memory.stopped = true;
+        self.state = CpuState::Stopped;
+    }
+
+    /// Jumps to the highest priority pending interrupt if IME is set,
+    /// returning the cycles spent doing so.
+    fn service_interrupt(&mut self, memory: &mut Bus) -> Option<u8> {
+        if !self.ime {
+            return None;
+        }
+
+        let interrupt = memory.interrupts.next()?;
+        memory.interrupts.acknowledge(interrupt);
+        self.ime = false;
+
+        self.registers.sp = self.registers.sp.wrapping_sub(2);
+        memory.write_u16(self.registers.sp, self.registers.pc);
+        self.registers.pc = interrupt.vector();
+
+        Some(DISPATCH_CYCLES)
+    }
+}
+
+#[cfg(test)]
+mod cpu_tests {
+    use super::{CpuState, CPU};
+    use crate::emulator::{bus::Bus, interrupts::Interrupt};
+
+    fn setup() -> (CPU, Bus) {
+        let mut cpu = CPU::default();
+        cpu.load_instructions();
+        cpu.registers.pc = 0x0200;
+        cpu.registers.sp = 0x1000;
+
+        (cpu, Bus::flat())
+    }
+
+    #[test]
+    fn test_dispatch_pushes_pc_and_jumps_to_vector() {
+        let (mut cpu, mut memory) = setup();
+        cpu.ime = true;
+        memory.interrupts.enable = Interrupt::Timer.mask();
+        memory.interrupts.request(Interrupt::Timer);
+
+        let cycles = cpu.service_interrupt(&mut memory);
+
+        assert_eq!(cycles, Some(20));
+        assert_eq!(cpu.registers.pc, 0x50);
+        assert_eq!(cpu.registers.sp, 0x0FFE);
+        assert_eq!(memory.read_u16(0x0FFE), 0x0200);
+        assert_eq!(memory.interrupts.flag, 0);
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn test_no_dispatch_without_ime() {
+        let (mut cpu, mut memory) = setup();
+        memory.interrupts.enable = Interrupt::VBlank.mask();
+        memory.interrupts.request(Interrupt::VBlank);
+
+        cpu.execute(&mut memory).unwrap();
+
+        // NOP executed instead
+        assert_eq!(cpu.registers.pc, 0x0201);
+        assert_eq!(memory.interrupts.flag, Interrupt::VBlank.mask());
+    }
+
+    #[test]
+    fn test_ei_is_delayed_by_one_instruction() {
+        let (mut cpu, mut memory) = setup();
+        memory.interrupts.enable = Interrupt::VBlank.mask();
+        memory.interrupts.request(Interrupt::VBlank);
+        memory.write_u8(0x0200, 0xFB); // EI
+        memory.write_u8(0x0201, 0x00); // NOP
+
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.registers.pc, 0x0201);
+
+        // the instruction after EI still runs before the interrupt
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.registers.pc, 0x0202);
+
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.registers.pc, 0x40);
+    }
+
+    #[test]
+    fn test_di_after_ei_blocks_interrupts() {
+        let (mut cpu, mut memory) = setup();
+        memory.interrupts.enable = Interrupt::VBlank.mask();
+        memory.interrupts.request(Interrupt::VBlank);
+        memory.write_u8(0x0200, 0xFB); // EI
+        memory.write_u8(0x0201, 0xF3); // DI
+
+        cpu.execute(&mut memory).unwrap();
+        cpu.execute(&mut memory).unwrap();
+        cpu.execute(&mut memory).unwrap();
+
+        assert_eq!(cpu.registers.pc, 0x0203);
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn test_reti_enables_ime() {
+        let (mut cpu, mut memory) = setup();
+        memory.write_u8(0x0200, 0xD9); // RETI
+        memory.write_u16(0x1000, 0x1234);
+
+        cpu.execute(&mut memory).unwrap();
+
+        assert_eq!(cpu.registers.pc, 0x1234);
+        assert!(cpu.ime);
+    }
+
+    #[test]
+    fn test_halt_sleeps_until_interrupt_requested() {
+        let (mut cpu, mut memory) = setup();
+        memory.interrupts.enable = Interrupt::Timer.mask();
+        memory.write_u8(0x0200, 0x76); // HALT
+
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.state(), CpuState::Halted);
+
+        assert_eq!(cpu.execute(&mut memory).unwrap(), 4);
+        assert_eq!(cpu.registers.pc, 0x0201);
+
+        // with IME off the CPU wakes up and carries on without servicing
+        memory.interrupts.request(Interrupt::Timer);
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.state(), CpuState::Running);
+        assert_eq!(cpu.registers.pc, 0x0202);
+    }
+
+    #[test]
+    fn test_halt_wakes_into_interrupt_with_ime() {
+        let (mut cpu, mut memory) = setup();
+        cpu.ime = true;
+        memory.interrupts.enable = Interrupt::Timer.mask();
+        memory.write_u8(0x0200, 0x76); // HALT
+
+        cpu.execute(&mut memory).unwrap();
+        memory.interrupts.request(Interrupt::Timer);
+
+        assert_eq!(cpu.execute(&mut memory).unwrap(), 20);
+        assert_eq!(cpu.registers.pc, 0x50);
+        assert_eq!(memory.read_u16(0x0FFE), 0x0201);
+    }
+
+    #[test]
+    fn test_halt_bug_repeats_next_byte() {
+        let (mut cpu, mut memory) = setup();
+        memory.interrupts.enable = Interrupt::Timer.mask();
+        memory.interrupts.request(Interrupt::Timer);
+        memory.write_u8(0x0200, 0x76); // HALT
+        memory.write_u8(0x0201, 0x3C); // INC A
+
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.state(), CpuState::Running);
+
+        cpu.execute(&mut memory).unwrap();
+        cpu.execute(&mut memory).unwrap();
+
+        assert_eq!(cpu.registers.a, 2);
+        assert_eq!(cpu.registers.pc, 0x0202);
+    }
+
+    #[test]
+    fn test_halt_bug_reads_opcode_as_operand() {
+        let (mut cpu, mut memory) = setup();
+        memory.interrupts.enable = Interrupt::Timer.mask();
+        memory.interrupts.request(Interrupt::Timer);
+        memory.write_u8(0x0200, 0x76); // HALT
+        memory.write_u8(0x0201, 0x3E); // LD A, 0x14
+        memory.write_u8(0x0202, 0x14);
+
+        cpu.execute(&mut memory).unwrap();
+        cpu.execute(&mut memory).unwrap();
+
+        // LD A, 0x3E, then 0x14 runs as INC D
+        assert_eq!(cpu.registers.a, 0x3E);
+        assert_eq!(cpu.registers.pc, 0x0202);
+        let d = cpu.registers.d;
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.registers.d, d.wrapping_add(1));
+        assert_eq!(cpu.registers.pc, 0x0203);
+    }
+
+    #[test]
+    fn test_prefix_wraps_past_end_of_memory() {
+        let (mut cpu, mut memory) = setup();
+        cpu.registers.pc = 0xFFFF;
+        cpu.registers.a = 0x12;
+        memory.write_u8(0xFFFF, 0xCB);
+        memory.write_u8(0x0000, 0x37); // SWAP A
+
+        cpu.execute(&mut memory).unwrap();
+
+        assert_eq!(cpu.registers.a, 0x21);
+        assert_eq!(cpu.registers.pc, 0x0001);
+    }
+
+    #[test]
+    fn test_stack_wraps_around_memory() {
+        let (mut cpu, mut memory) = setup();
+        cpu.registers.sp = 0x0001;
+        cpu.registers.b = 0x12;
+        cpu.registers.c = 0x34;
+        memory.write_u8(0x0200, 0xC5); // PUSH BC
+        memory.write_u8(0x0201, 0xCD); // CALL 0x0300
+        memory.write_u16(0x0202, 0x0300);
+        memory.write_u8(0x0300, 0xC9); // RET
+        memory.write_u8(0x0204, 0xD1); // POP DE
+
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.registers.sp, 0xFFFF);
+        assert_eq!(memory.read_u16(0xFFFF), 0x1234);
+
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.registers.sp, 0xFFFD);
+        assert_eq!(memory.read_u16(0xFFFD), 0x0204);
+
+        cpu.execute(&mut memory).unwrap();
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.registers.sp, 0x0001);
+        assert_eq!((cpu.registers.d, cpu.registers.e), (0x12, 0x34));
+    }
+
+    #[test]
+    fn test_stop_waits_for_joypad_and_resets_div() {
+        // the real timer behind DIV rather than the flat test memory
+        let (mut cpu, _) = setup();
+        let mut memory = Bus::default();
+        cpu.registers.pc = 0xC000;
+        memory.write_u8(0xC000, 0x10); // STOP
+        memory.write_u8(0xC001, 0x00);
+        // 256 T-cycles per DIV increment
+        for _ in 0..64 {
+            memory.tick(4);
+        }
+        assert_eq!(memory.read_u8(0xFF04), 1);
+        // left over from polling with IME off
+        memory.interrupts.request(Interrupt::Joypad);
+
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.state(), CpuState::Stopped);
+        assert_eq!(memory.read_u8(0xFF04), 0);
+
+        memory.interrupts.request(Interrupt::Timer);
+        for _ in 0..1000 {
+            let cycles = cpu.execute(&mut memory).unwrap();
+            memory.tick(cycles);
+        }
+        assert_eq!(cpu.state(), CpuState::Stopped);
+        assert_eq!(memory.read_u8(0xFF04), 0);
+
+        memory.interrupts.request(Interrupt::Joypad);
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(cpu.state(), CpuState::Running);
+        assert_eq!(cpu.registers.pc, 0xC003);
+
+        for _ in 0..64 {
+            memory.tick(4);
+        }
+        assert_eq!(memory.read_u8(0xFF04), 1);
     }
 }
@@ -0,0 +1,116 @@
+pub const DMA_ADDRESS: u16 = 0xFF46;
+
+/// Bytes copied per transfer, the size of OAM
+pub const TRANSFER_LENGTH: u8 = 0xA0;
+/// M-cycles between the write to DMA and the first byte being copied
+const START_DELAY: u8 = 1;
+
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+    source: u16,
+    /// Next byte to copy
+    index: u8,
+}
+
+/// OAM DMA controller behind 0xFF46. Writing XX copies XX00-XX9F into OAM,
+/// one byte per M-cycle, during which the CPU can only reach HRAM and the
+/// I/O registers.
+#[derive(Debug, Default)]
+pub struct Dma {
+    /// Last value written, reads back even once the transfer is done
+    register: u8,
+    active: Option<Transfer>,
+    /// Transfer waiting out the start delay. A running one keeps going
+    /// until this replaces it.
+    pending: Option<(Transfer, u8)>,
+}
+
+impl Dma {
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+        let transfer = Transfer {
+            source: (value as u16) << 8,
+            index: 0,
+        };
+        self.pending = Some((transfer, START_DELAY));
+    }
+
+    /// A transfer is copying bytes and holds the bus
+    pub fn active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Whether a CPU access to `address` loses out to the transfer, only
+    /// the I/O registers and HRAM are on a separate bus
+    pub fn blocks(&self, address: u16) -> bool {
+        self.active() && address < 0xFF00
+    }
+
+    /// Advances by one M-cycle, returning the source address of the byte to
+    /// copy and its offset in OAM, if any
+    pub fn step(&mut self) -> Option<(u16, u8)> {
+        match self.pending {
+            Some((transfer, 0)) => {
+                self.active = Some(transfer);
+                self.pending = None;
+            }
+            Some((transfer, delay)) => self.pending = Some((transfer, delay - 1)),
+            None => {}
+        }
+
+        let transfer = self.active.as_mut()?;
+        let index = transfer.index;
+        let source = transfer.source + index as u16;
+        transfer.index += 1;
+        if transfer.index == TRANSFER_LENGTH {
+            self.active = None;
+        }
+        Some((source, index))
+    }
+}
+
+#[cfg(test)]
+mod dma_tests {
+    use super::{Dma, TRANSFER_LENGTH};
+
+    #[test]
+    fn test_transfer_sequence() {
+        let mut dma = Dma::default();
+        dma.write(0xC1);
+
+        // one M-cycle to get going
+        assert_eq!(dma.step(), None);
+        assert!(!dma.active());
+
+        assert_eq!(dma.step(), Some((0xC100, 0)));
+        assert!(dma.blocks(0xC000));
+        assert!(!dma.blocks(0xFF80));
+        assert!(!dma.blocks(0xFF46));
+
+        let copied = (1..TRANSFER_LENGTH).map_while(|_| dma.step()).count();
+        assert_eq!(copied, TRANSFER_LENGTH as usize - 1);
+        assert!(!dma.active());
+        assert_eq!(dma.step(), None);
+        assert_eq!(dma.read(), 0xC1);
+    }
+
+    #[test]
+    fn test_restart_mid_transfer() {
+        let mut dma = Dma::default();
+        dma.write(0xC1);
+        for _ in 0..11 {
+            dma.step();
+        }
+
+        dma.write(0xD0);
+
+        // the old transfer carries on through the new one's start delay
+        assert_eq!(dma.step(), Some((0xC10A, 0x0A)));
+        assert_eq!(dma.step(), Some((0xD000, 0)));
+        assert_eq!(dma.step(), Some((0xD001, 1)));
+    }
+}
@@ -1,21 +1,26 @@
 #![allow(unused)]
 mod arithmetic;
+mod bitops;
+#[cfg(test)]
+mod conformance;
 mod increment;
 mod jump;
 mod load;
+mod opcodes;
 mod stack;
 mod utils;
 
-use std::fs;
-
 use crate::emulator::instructions::{self, jump::get_jump_operands};
 
-use super::{cpu::cpu_registers::CPURegisters, memory::Memory};
+use super::{bus::Bus, cpu::cpu_registers::CPURegisters};
 use arithmetic::*;
+use bitops::*;
 use increment::*;
 use jump::*;
 use load::*;
 use stack::*;
+use opcodes::{CB_INSTRUCTION_DATA, INSTRUCTION_DATA};
+pub use utils::{ExecutionError, OpKind};
 use utils::{Args, BranchArgs, InstructionData, InstructionError, Operands, Ret};
 
 #[derive(Clone, Debug)]
@@ -34,113 +39,198 @@ impl Default for Instruction {
 }
 
 impl Instruction {
-    fn exec(&self, params: Operands, branch_args: BranchArgs) -> u8 {
-        match (self.func)(params, branch_args) {
-            Ok(ret) => ret,
-            Err(e) => panic!("{}", e),
-        }
+    fn exec<'a>(
+        &self,
+        params: Operands<'a>,
+        branch_args: BranchArgs,
+    ) -> Result<u8, InstructionError<'a>> {
+        (self.func)(params, branch_args)
     }
 }
 
 pub fn execute_instruction(
     instruction: &Instruction,
     registers: &mut CPURegisters,
-    memory: &mut Memory,
-) -> u8 {
-    let opcode = memory.read_u8(registers.pc);
+    memory: &mut Bus,
+) -> Result<u8, ExecutionError> {
+    let operands = registers.pc.wrapping_add(1);
+    execute_instruction_at(instruction, registers, memory, operands)
+}
+
+/// Executes the instruction whose opcode is at PC, fetching the bytes after
+/// the opcode from `operands` on. That's normally the next address, but
+/// after the HALT bug PC isn't incremented past the opcode, so the opcode
+/// byte is fetched again.
+pub fn execute_instruction_at(
+    instruction: &Instruction,
+    registers: &mut CPURegisters,
+    memory: &mut Bus,
+    operands: u16,
+) -> Result<u8, ExecutionError> {
+    let pc = registers.pc;
+    // errors report the registers as they were before the fetch
+    let snapshot = registers.clone();
+
+    // Prefixed instructions are decoded from the byte following 0xCB
+    let prefixed = memory.read_u8(pc) == 0xCB;
+    let opcode = if prefixed {
+        memory.read_u8(operands)
+    } else {
+        memory.read_u8(pc)
+    };
+    let full_opcode = if prefixed {
+        0xCB00 | opcode as u16
+    } else {
+        opcode as u16
+    };
 
     let value: Option<Ret> = match instruction.data.bytes {
         1 => None,
-        2 => Some(Ret::U8(memory.read_u8(registers.pc + 1))),
-        3 => Some(Ret::U16(memory.read_u16(registers.pc + 1))),
-        _ => panic!("Bytes is invalid"),
+        2 => Some(Ret::U8(memory.read_u8(operands))),
+        3 => Some(Ret::U16(memory.read_u16(operands))),
+        bytes => {
+            return Err(ExecutionError::new(
+                full_opcode,
+                pc,
+                &snapshot,
+                format!("Invalid instruction length {}", bytes),
+            ))
+        }
     };
 
-    // TODO: debug
-    println!(
-        "Executing instruction - {:#04x}:\n\tInstruction Data - {:?}\n\tPC - {}\n\tSP - {}",
-        opcode, instruction.data, registers.pc, registers.sp
-    );
-    registers.pc += (instruction.data.bytes) as u16;
-
-    println!("{:?}", instruction);
-
-    let get_operands_result: Result<Args, InstructionError> =
-        match instruction.data.mnemonic.as_str() {
-            "NOP" => Ok((Operands::None, None)),
-            "LD" => get_ld_operands(registers, memory, opcode, value),
-            "ADD" | "ADC" | "SUB" | "SBC" | "XOR" | "OR" | "AND" | "CP" => {
-                get_arithmetic_operands(registers, memory, opcode, value)
-            }
-            "INC" | "DEC" => get_ncrement_operands(registers, memory, opcode, value),
-            "JP" | "JR" => get_jump_operands(registers, memory, opcode, value),
-            "PUSH" | "POP"  => get_stack_operands(registers, memory, opcode, value),
-            "RET" | "RETI" => get_ret_operands(registers, memory, opcode, value),
-            "CALL" => get_call_operands(registers, memory, opcode, value),
-            _ => panic!(
-                "{}:\n\tInstruction Data - {:?}\n\tPC - {}\n\tSP - {}",
-                InstructionError::UnimplementedError(opcode),
-                instruction.data,
-                registers.pc,
-                registers.sp
-            ),
-        };
-
-    // TODO: debug
-    println!("\tOperands - {:?}", get_operands_result);
-
-    let instruction_cycles: u8 = match get_operands_result {
-        Ok((operands, condition)) => instruction.exec(
-            operands,
-            BranchArgs {
-                cycles: instruction.data.cycles.clone(),
-                condition,
-            },
-        ),
-        Err(e) => panic!("{}", e),
+    registers.pc = operands.wrapping_add(instruction.data.bytes as u16 - 1);
+
+    let get_operands_result: Result<Args, InstructionError> = match instruction.data.kind {
+        // These only change CPU state, which the CPU applies once they've executed
+        OpKind::Nop | OpKind::Ei | OpKind::Di | OpKind::Halt | OpKind::Stop => {
+            Ok((Operands::None, None))
+        }
+        OpKind::Ld | OpKind::Ldh => get_ld_operands(registers, memory, opcode, value),
+        OpKind::Add
+        | OpKind::Adc
+        | OpKind::Sub
+        | OpKind::Sbc
+        | OpKind::Xor
+        | OpKind::Or
+        | OpKind::And
+        | OpKind::Cp => get_arithmetic_operands(registers, memory, opcode, value),
+        OpKind::Inc | OpKind::Dec => get_ncrement_operands(registers, memory, opcode, value),
+        OpKind::Jp | OpKind::Jr => get_jump_operands(registers, memory, opcode, value),
+        OpKind::Push | OpKind::Pop => get_stack_operands(registers, memory, opcode, value),
+        OpKind::Ret | OpKind::Reti => get_ret_operands(registers, memory, opcode, value),
+        OpKind::Call => get_call_operands(registers, memory, opcode, value),
+        OpKind::Rst => get_rst_operands(registers, memory, opcode, value),
+        OpKind::Daa
+        | OpKind::Cpl
+        | OpKind::Scf
+        | OpKind::Ccf
+        | OpKind::Rlca
+        | OpKind::Rrca
+        | OpKind::Rla
+        | OpKind::Rra => get_accumulator_operands(registers, memory, opcode, value),
+        OpKind::Rlc
+        | OpKind::Rrc
+        | OpKind::Rl
+        | OpKind::Rr
+        | OpKind::Sla
+        | OpKind::Sra
+        | OpKind::Swap
+        | OpKind::Srl
+        | OpKind::Bit
+        | OpKind::Res
+        | OpKind::Set => get_bitops_operands(registers, memory, opcode, value),
+        // The prefix is resolved by the caller picking from the CB table
+        OpKind::Prefix | OpKind::Illegal => Err(InstructionError::UnimplementedError(opcode)),
     };
 
-    instruction_cycles
+    let result = get_operands_result
+        .and_then(|(operands, condition)| {
+            instruction.exec(
+                operands,
+                BranchArgs {
+                    cycles: instruction.data.cycles.to_vec(),
+                    condition,
+                },
+            )
+        })
+        .map_err(|e| e.to_string());
+
+    // memory operands were written through the bus latch
+    match result {
+        Ok(_) => memory.commit(),
+        Err(_) => memory.discard(),
+    }
+
+    result.map_err(|reason| ExecutionError::new(full_opcode, pc, &snapshot, reason))
 }
 
 pub fn fetch_instructions() -> Vec<Instruction> {
-    let json_string = fs::read_to_string("instructions.json").expect("File not found");
-
-    let json: serde_json::Value = serde_json::from_str(json_string.as_str()).expect("Invalid JSON");
-
-    let array = json.get("instructions").unwrap().to_string();
-
-    let instructions_data: Vec<InstructionData> = serde_json::from_str(array.as_str()).unwrap();
-
-    instructions_data
-        .into_iter()
-        .map(|data| {
-            let func = match data.mnemonic.as_str() {
-                "LD" => ld,
-                "NOP" => nop,
-                "ADD" => add,
-                "ADC" => adc,
-                "SUB" => sub,
-                "SBC" => sbc,
-                "XOR" => xor,
-                "AND" => and,
-                "OR" => or,
-                "CP" => cp,
-                "INC" => inc,
-                "DEC" => dec,
-                "JP" => jp,
-                "JR" => jr,
-                "PUSH" | "POP" => push_pop,
-                "RET" | "RETI" => ret,
-                "CALL" => call,
-                _ => nop,
-            };
-
-            Instruction { data, func }
+    INSTRUCTION_DATA
+        .iter()
+        .map(|&data| Instruction {
+            data,
+            func: handler(data.kind),
         })
         .collect()
 }
 
+pub fn fetch_cb_instructions() -> Vec<Instruction> {
+    CB_INSTRUCTION_DATA
+        .iter()
+        .map(|&data| Instruction {
+            data,
+            func: handler(data.kind),
+        })
+        .collect()
+}
+
+fn handler(kind: OpKind) -> fn(Operands, BranchArgs) -> Result<u8, InstructionError> {
+    match kind {
+        OpKind::Nop
+        | OpKind::Halt
+        | OpKind::Stop
+        | OpKind::Di
+        | OpKind::Ei
+        | OpKind::Prefix
+        | OpKind::Illegal => nop,
+        OpKind::Ld | OpKind::Ldh => ld,
+        OpKind::Add => add,
+        OpKind::Adc => adc,
+        OpKind::Sub => sub,
+        OpKind::Sbc => sbc,
+        OpKind::Xor => xor,
+        OpKind::And => and,
+        OpKind::Or => or,
+        OpKind::Cp => cp,
+        OpKind::Inc => inc,
+        OpKind::Dec => dec,
+        OpKind::Jp => jp,
+        OpKind::Jr => jr,
+        OpKind::Push | OpKind::Pop => push_pop,
+        OpKind::Ret | OpKind::Reti => ret,
+        OpKind::Call | OpKind::Rst => call,
+        OpKind::Daa => daa,
+        OpKind::Cpl => cpl,
+        OpKind::Scf => scf,
+        OpKind::Ccf => ccf,
+        OpKind::Rlca => rlca,
+        OpKind::Rrca => rrca,
+        OpKind::Rla => rla,
+        OpKind::Rra => rra,
+        OpKind::Rlc => rlc,
+        OpKind::Rrc => rrc,
+        OpKind::Rl => rl,
+        OpKind::Rr => rr,
+        OpKind::Sla => sla,
+        OpKind::Sra => sra,
+        OpKind::Swap => swap,
+        OpKind::Srl => srl,
+        OpKind::Bit => bit,
+        OpKind::Res => res,
+        OpKind::Set => set,
+    }
+}
+
 pub fn nop(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError> {
     Ok(branch_args.cycles[0])
 }
@@ -148,15 +238,31 @@ pub fn nop(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instru
 #[cfg(test)]
 mod instruction_integration_tests {
     use crate::emulator::{
-        cpu::cpu_registers::{convert_u16_to_two_u8s, CPURegisters}, instructions::jump, memory::{Memory, U16Wrapper}
+        cpu::cpu_registers::{convert_u16_to_two_u8s, CPURegisters}, instructions::jump, bus::Bus, memory::U16Wrapper
     };
 
-    use super::{execute_instruction, fetch_instructions, Instruction};
+    use super::{execute_instruction, fetch_cb_instructions, fetch_instructions, Instruction};
+
+    #[test]
+    fn test_error_snapshots_registers_before_fetch() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        registers.pc = 0x0200;
+        memory.write_u8(0x0200, 0xD3);
+
+        let e = execute_instruction(&instructions[0xD3], &mut registers, &mut memory).unwrap_err();
+
+        assert_eq!(e.opcode, 0xD3);
+        assert_eq!(e.pc, 0x0200);
+        assert_eq!(e.registers.pc, 0x0200);
+    }
 
     #[test]
     fn test_execute_ld_rx_rx_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -171,7 +277,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.b, 60);
@@ -180,7 +287,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_ld_r16_n16_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         let desired_value = 6666;
@@ -198,7 +305,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 3);
         assert_eq!(registers.b, expected_values.0);
@@ -210,7 +318,7 @@ mod instruction_integration_tests {
         let target_address = 0x0101;
 
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -226,7 +334,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.b, desired_result);
@@ -238,7 +347,7 @@ mod instruction_integration_tests {
         let desired_result: u8 = 69;
 
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -255,7 +364,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 3);
         assert_eq!(memory.read_u8(address), desired_result);
@@ -265,7 +375,7 @@ mod instruction_integration_tests {
         let desired_result: u8 = 69;
 
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -283,7 +393,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 3);
         assert_eq!(registers.a, desired_result);
@@ -291,7 +402,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_add_rx_rx_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -309,7 +420,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.a, desired_result);
@@ -317,7 +429,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_adc_rx_rx_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -335,7 +447,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.a, desired_result);
@@ -343,7 +456,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_sub_rx_rx_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -361,7 +474,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.a, desired_result);
@@ -369,7 +483,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_sbc_rx_rx_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -387,7 +501,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.a, desired_result);
@@ -395,7 +510,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_jp_z_true_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -408,11 +523,7 @@ mod instruction_integration_tests {
         registers.f = 0b1000_0000;
 
         let instr = &instructions[instruction as usize];
-        let cycles = execute_instruction(
-            instr,
-            &mut registers,
-            &mut memory,
-        );
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
 
         assert_eq!(registers.pc, 0xAFAF);
         assert_eq!(cycles, instr.data.cycles[0])
@@ -420,7 +531,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_jp_z_false_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -433,11 +544,7 @@ mod instruction_integration_tests {
         registers.f = 0b0000_0000; // condition should result in false
 
         let instr = &instructions[instruction as usize];
-        let cycles = execute_instruction(
-            instr,
-            &mut registers,
-            &mut memory,
-        );
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
 
         assert_eq!(registers.pc, 3);
         assert_eq!(cycles, instr.data.cycles[1])
@@ -446,7 +553,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_push_af_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -466,7 +573,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(memory.read_u8(99), 0x0F);
@@ -476,7 +584,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_pop_bc_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         assert_eq!(registers.pc, 0);
@@ -500,7 +608,8 @@ mod instruction_integration_tests {
             &instructions[instruction as usize],
             &mut registers,
             &mut memory,
-        );
+        )
+        .unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.c, 0x0F);
@@ -510,7 +619,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_ret_nz_true_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         // ensure pre-conditions
@@ -530,11 +639,7 @@ mod instruction_integration_tests {
         memory.write_u8(registers.sp + 1, 0xF0);
 
         let instr = &instructions[instruction as usize];
-        let cycles = execute_instruction(
-            instr,
-            &mut registers,
-            &mut memory,
-        );
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
 
         assert_eq!(registers.pc, 0xF00F);
         assert_eq!(registers.sp, 102);
@@ -543,7 +648,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_ret_nz_false_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         // ensure pre-conditions
@@ -563,11 +668,7 @@ mod instruction_integration_tests {
         memory.write_u8(registers.sp + 1, 0xF0);
 
         let instr = &instructions[instruction as usize];
-        let cycles = execute_instruction(
-            instr,
-            &mut registers,
-            &mut memory,
-        );
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
 
         assert_eq!(registers.pc, 1);
         assert_eq!(registers.sp, 100);
@@ -577,7 +678,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_call_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         let start_address = 0x44AA; 
@@ -597,11 +698,7 @@ mod instruction_integration_tests {
         memory.write_u16(start_address + 1, jump_address);
 
         let instr = &instructions[instruction as usize];
-        let cycles = execute_instruction(
-            instr,
-            &mut registers,
-            &mut memory,
-        );
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
 
         // Check that pc was written to stack
         let stack_var= memory.read_u16(98);
@@ -618,7 +715,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_call_nz_true_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         let start_address = 0x44AA; 
@@ -638,11 +735,7 @@ mod instruction_integration_tests {
         memory.write_u16(start_address + 1, jump_address);
 
         let instr = &instructions[instruction as usize];
-        let cycles = execute_instruction(
-            instr,
-            &mut registers,
-            &mut memory,
-        );
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
 
         // Check that pc was written to stack
         let stack_var= memory.read_u16(98);
@@ -659,7 +752,7 @@ mod instruction_integration_tests {
     #[test]
     fn test_execute_call_nz_false_instruction() {
         let mut registers = CPURegisters::default();
-        let mut memory = Memory::default();
+        let mut memory = Bus::flat();
         let instructions: Vec<Instruction> = fetch_instructions();
 
         let start_address = 0x44AA; 
@@ -679,11 +772,7 @@ mod instruction_integration_tests {
         memory.write_u16(start_address + 1, jump_address);
 
         let instr = &instructions[instruction as usize];
-        let cycles = execute_instruction(
-            instr,
-            &mut registers,
-            &mut memory,
-        );
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
 
         // Check that pc was written to stack
         let stack_var= memory.read_u16(98);
@@ -697,4 +786,246 @@ mod instruction_integration_tests {
         // Ensure cycles data is correct
         assert_eq!(cycles, instr.data.cycles[1])
     }
+
+    #[test]
+    fn test_execute_cb_swap_a_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let cb_instructions: Vec<Instruction> = fetch_cb_instructions();
+
+        // First instruction should be: SWAP A
+        let instruction = 0x37;
+        memory.write_u8(0x0, 0xCB);
+        memory.write_u8(0x1, instruction);
+        registers.a = 0xF1;
+
+        let instr = &cb_instructions[instruction as usize];
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
+
+        assert_eq!(registers.pc, 2);
+        assert_eq!(registers.a, 0x1F);
+        assert_eq!(registers.f, 0);
+        assert_eq!(cycles, 8);
+    }
+    #[test]
+    fn test_execute_cb_set_hlmem_instruction() {
+        let target_address = 0x0101;
+
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let cb_instructions: Vec<Instruction> = fetch_cb_instructions();
+
+        // First instruction should be: SET 3, [HL]
+        let instruction = 0xDE;
+        memory.write_u8(0x0, 0xCB);
+        memory.write_u8(0x1, instruction);
+        registers.set_hl(target_address);
+
+        let instr = &cb_instructions[instruction as usize];
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
+
+        assert_eq!(registers.pc, 2);
+        assert_eq!(memory.read_u8(target_address), 0b0000_1000);
+        assert_eq!(cycles, 16);
+    }
+    #[test]
+    fn test_execute_cb_bit_hlmem_instruction() {
+        let target_address = 0x0101;
+
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let cb_instructions: Vec<Instruction> = fetch_cb_instructions();
+
+        // First instruction should be: BIT 7, [HL]
+        let instruction = 0x7E;
+        memory.write_u8(0x0, 0xCB);
+        memory.write_u8(0x1, instruction);
+        memory.write_u8(target_address, 0x7F);
+        registers.set_hl(target_address);
+
+        let instr = &cb_instructions[instruction as usize];
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
+
+        assert_eq!(registers.pc, 2);
+        assert_eq!(registers.f, 0b1010_0000);
+        assert_eq!(cycles, 12);
+    }
+
+    #[test]
+    fn test_execute_rst_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        registers.pc = 0x0150;
+        registers.sp = 100;
+
+        // First instruction should be: RST $28
+        let instruction = 0xEF;
+        memory.write_u8(0x0150, instruction);
+
+        let instr = &instructions[instruction as usize];
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
+
+        assert_eq!(registers.pc, 0x0028);
+        assert_eq!(registers.sp, 98);
+        assert_eq!(memory.read_u16(98), 0x0151);
+        assert_eq!(cycles, 16);
+    }
+    #[test]
+    fn test_execute_ldh_a8mem_a_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        // First instruction should be: LDH [a8], A
+        let instruction = 0xE0;
+        memory.write_u8(0x0, instruction);
+        memory.write_u8(0x1, 0x80);
+        registers.a = 69;
+
+        execute_instruction(
+            &instructions[instruction as usize],
+            &mut registers,
+            &mut memory,
+        )
+        .unwrap();
+
+        assert_eq!(registers.pc, 2);
+        assert_eq!(memory.read_u8(0xFF80), 69);
+    }
+    #[test]
+    fn test_execute_ld_a_cmem_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        // First instruction should be: LD A, [C]
+        let instruction = 0xF2;
+        memory.write_u8(0x0, instruction);
+        memory.write_u8(0xFF90, 69);
+        registers.c = 0x90;
+
+        execute_instruction(
+            &instructions[instruction as usize],
+            &mut registers,
+            &mut memory,
+        )
+        .unwrap();
+
+        assert_eq!(registers.pc, 1);
+        assert_eq!(registers.a, 69);
+    }
+    #[test]
+    fn test_execute_ld_a_hlmem_decrement_instruction() {
+        let target_address = 0x0101;
+
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        // First instruction should be: LD A, [HL-]
+        let instruction = 0x3A;
+        memory.write_u8(0x0, instruction);
+        memory.write_u8(target_address, 69);
+        registers.set_hl(target_address);
+
+        execute_instruction(
+            &instructions[instruction as usize],
+            &mut registers,
+            &mut memory,
+        )
+        .unwrap();
+
+        assert_eq!(registers.a, 69);
+        assert_eq!(registers.get_hl(), target_address - 1);
+    }
+    #[test]
+    fn test_execute_ld_r8_n8_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        // First instruction should be: LD E, n8
+        let instruction = 0x1E;
+        memory.write_u8(0x0, instruction);
+        memory.write_u8(0x1, 69);
+
+        execute_instruction(
+            &instructions[instruction as usize],
+            &mut registers,
+            &mut memory,
+        )
+        .unwrap();
+
+        assert_eq!(registers.pc, 2);
+        assert_eq!(registers.e, 69);
+    }
+    #[test]
+    fn test_execute_ld_a16mem_sp_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        // First instruction should be: LD [a16], SP
+        let instruction = 0x08;
+        let address: u16 = 0x0101;
+        memory.write_u8(0x0, instruction);
+        memory.write_u16(0x1, address);
+        registers.sp = 0xBEEF;
+
+        execute_instruction(
+            &instructions[instruction as usize],
+            &mut registers,
+            &mut memory,
+        )
+        .unwrap();
+
+        assert_eq!(registers.pc, 3);
+        assert_eq!(memory.read_u16(address), 0xBEEF);
+    }
+    #[test]
+    fn test_execute_ld_hl_sp_e8_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        // First instruction should be: LD HL, SP + e8
+        let instruction = 0xF8;
+        memory.write_u8(0x0, instruction);
+        memory.write_u8(0x1, -1i8 as u8);
+        registers.sp = 0x00FF;
+        registers.f = 0b1100_0000;
+
+        execute_instruction(
+            &instructions[instruction as usize],
+            &mut registers,
+            &mut memory,
+        )
+        .unwrap();
+
+        assert_eq!(registers.pc, 2);
+        assert_eq!(registers.get_hl(), 0x00FE);
+        // flags come from the unsigned low byte addition 0xFF + 0xFF
+        assert_eq!(registers.f, 0b0011_0000);
+    }
+    #[test]
+    fn test_execute_add_sp_e8_instruction() {
+        let mut registers = CPURegisters::default();
+        let mut memory = Bus::flat();
+        let instructions: Vec<Instruction> = fetch_instructions();
+
+        // First instruction should be: ADD SP, e8
+        let instruction = 0xE8;
+        memory.write_u8(0x0, instruction);
+        memory.write_u8(0x1, 0x08);
+        registers.sp = 0x1008;
+
+        let instr = &instructions[instruction as usize];
+        let cycles = execute_instruction(instr, &mut registers, &mut memory).unwrap();
+
+        assert_eq!(registers.sp, 0x1010);
+        assert_eq!(registers.f, 0b0010_0000);
+        assert_eq!(cycles, 16);
+    }
 }
@@ -1,7 +1,4 @@
-use crate::emulator::{
-    cpu::cpu_registers::CPURegisters,
-    memory::{Memory, U16Wrapper},
-};
+use crate::emulator::{bus::Bus, cpu::cpu_registers::CPURegisters, memory::U16Wrapper};
 
 use super::utils::{Args, BranchArgs, InstructionError, Operands, Ret, Word};
 
@@ -22,6 +19,10 @@ pub fn add(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instru
                 *target = add_u16_with_flags(target.clone(), source, flags.unwrap());
                 Ok(branch_args.cycles[0])
             }
+            (Word::U16Mut(target), Word::U8(source)) => {
+                *target = add_sp_offset_with_flags(*target, source, flags.unwrap());
+                Ok(branch_args.cycles[0])
+            }
             (word1, word2) => {
                 return Err(InstructionError::IncorrectOperandsError(format!(
                     "Incorrect words {:?} , {:?} passed to add function add",
@@ -192,6 +193,82 @@ pub fn cp(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instruc
         return Err(InstructionError::InvalidOperandsError(operands));
     }
 }
+pub fn daa(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    if let Operands::One(target, flags) = operands {
+        match (target, flags) {
+            (Word::U8Mut(target), Some(flags)) => {
+                let negative = *flags & 0b0100_0000 != 0;
+                let half_carry = *flags & 0b0010_0000 != 0;
+                let mut carry = *flags & 0b0001_0000 != 0;
+
+                // after a subtraction only the flags say whether a digit borrowed
+                let mut correction = 0;
+                if half_carry || (!negative && (*target & 0x0f) > 0x09) {
+                    correction |= 0x06;
+                }
+                if carry || (!negative && *target > 0x99) {
+                    correction |= 0x60;
+                    carry = true;
+                }
+
+                *target = if negative {
+                    target.wrapping_sub(correction)
+                } else {
+                    target.wrapping_add(correction)
+                };
+
+                let zero = (*target == 0) as u8;
+                *flags = (zero << 7) | (*flags & 0b0100_0000) | ((carry as u8) << 4);
+                Ok(branch_args.cycles[0])
+            }
+            (word1, _) => Err(InstructionError::IncorrectOperandsError(format!(
+                "Incorrect word {:?} passed to function daa",
+                word1
+            ))),
+        }
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
+pub fn cpl(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    if let Operands::One(target, flags) = operands {
+        match (target, flags) {
+            (Word::U8Mut(target), Some(flags)) => {
+                *target = !*target;
+
+                // negative and half carry always set
+                *flags |= 0b0110_0000;
+                Ok(branch_args.cycles[0])
+            }
+            (word1, _) => Err(InstructionError::IncorrectOperandsError(format!(
+                "Incorrect word {:?} passed to function cpl",
+                word1
+            ))),
+        }
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
+pub fn scf(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    if let Operands::One(_, Some(flags)) = operands {
+        *flags = (*flags & 0b1000_0000) | 0b0001_0000;
+        Ok(branch_args.cycles[0])
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
+pub fn ccf(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    if let Operands::One(_, Some(flags)) = operands {
+        *flags = (*flags & 0b1000_0000) | (!*flags & 0b0001_0000);
+        Ok(branch_args.cycles[0])
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
 fn sub_with_flags(target: u8, source: u8, flags: &mut u8) -> u8 {
     let result: (u8, bool) = target.overflowing_sub(source);
     let carry = result.1 as u8;
@@ -244,9 +321,20 @@ fn add_u16_with_flags(target: u16, source: u16, flags: &mut u8) -> u16 {
     result.0
 }
 
+/// Adds a signed 8 bit offset to SP, as done by ADD SP, e8 and LD HL, SP + e8.
+/// Z and N are cleared, H and C come from the unsigned addition of the low byte.
+pub fn add_sp_offset_with_flags(sp: u16, offset: u8, flags: &mut u8) -> u16 {
+    let half_carry = (((sp & 0x0f) + (offset as u16 & 0x0f)) > 0x0f) as u8;
+    let carry = (((sp & 0xff) + offset as u16) > 0xff) as u8;
+
+    *flags = (half_carry << 5) | (carry << 4);
+
+    sp.wrapping_add_signed((offset as i8) as i16)
+}
+
 pub fn get_arithmetic_operands<'a>(
     registers: &'a mut CPURegisters,
-    mem: &'a mut Memory,
+    mem: &'a mut Bus,
     opcode: u8,
     value: Option<Ret>,
 ) -> Result<Args<'a>, InstructionError<'a>> {
@@ -310,6 +398,20 @@ pub fn get_arithmetic_operands<'a>(
                     _ => return Err(InstructionError::UnimplementedError(opcode)),
                 }
             }
+            // ADD SP, e8
+            0x8 if hi == 0xE => {
+                let value: u8 = if let Some(Ret::U8(x)) = value {
+                    x
+                } else {
+                    return Err(InstructionError::InvalidLiteral(value.unwrap()));
+                };
+
+                Operands::Two(
+                    Word::U16Mut(&mut registers.sp),
+                    Word::U8(value),
+                    Some(&mut registers.f),
+                )
+            }
             _ => return Err(InstructionError::UnimplementedError(opcode)),
         }
     };
@@ -317,10 +419,27 @@ pub fn get_arithmetic_operands<'a>(
     Ok((ops, None))
 }
 
+/// Operands for the instructions that work on A and F alone: DAA, CPL, SCF,
+/// CCF and the accumulator rotates.
+pub fn get_accumulator_operands<'a>(
+    registers: &'a mut CPURegisters,
+    mem: &'a mut Bus,
+    opcode: u8,
+    value: Option<Ret>,
+) -> Result<Args<'a>, InstructionError<'a>> {
+    match opcode {
+        0x07 | 0x0F | 0x17 | 0x1F | 0x27 | 0x2F | 0x37 | 0x3F => Ok((
+            Operands::One(Word::U8Mut(&mut registers.a), Some(&mut registers.f)),
+            None,
+        )),
+        _ => Err(InstructionError::UnimplementedError(opcode)),
+    }
+}
+
 #[cfg(test)]
 mod arithmetic_instruction_tests {
     use crate::emulator::instructions::*;
-    use arithmetic::{adc, add, sbc, sub, xor};
+    use arithmetic::{adc, add, ccf, cpl, daa, sbc, scf, sub, xor};
     use utils::Word;
 
     #[test]
@@ -340,10 +459,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, desired_result);
     }
@@ -363,10 +484,12 @@ mod arithmetic_instruction_tests {
         };
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(flags, 0b10000000);
         assert_eq!(target, desired_result);
@@ -388,10 +511,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
         assert_eq!(flags, 0b00010000);
         assert_eq!(target, desired_result);
     }
@@ -412,10 +537,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0b00010000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, desired_result);
     }
@@ -435,10 +562,12 @@ mod arithmetic_instruction_tests {
         };
         let mut flags = 0b0001_0000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(flags, 0b00000000); // zero flags should NOT be set because of carry
         assert_eq!(target, desired_result);
@@ -460,10 +589,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0b00010000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
         assert_eq!(flags, 0b00010000);
         assert_eq!(target, desired_result);
     }
@@ -485,10 +616,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0b00000000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, desired_result);
         assert_eq!(flags, 0b01000000);
@@ -509,10 +642,12 @@ mod arithmetic_instruction_tests {
         };
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(flags, 0b11000000);
         assert_eq!(target, desired_result);
@@ -534,10 +669,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
         assert_eq!(flags, 0b0111_0000);
         assert_eq!(target, desired_result);
     }
@@ -559,10 +696,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0b00010000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(flags, 0b0100_0000);
         assert_eq!(target, desired_result);
@@ -583,10 +722,12 @@ mod arithmetic_instruction_tests {
         };
         let mut flags = 0b00010000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(flags, 0b1100_0000);
         assert_eq!(target, desired_result);
@@ -609,10 +750,12 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0b00010000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
         assert_eq!(flags, 0b0111_0000); // both carry and half carry should be set
         assert_eq!(target, desired_result);
     }
@@ -634,10 +777,106 @@ mod arithmetic_instruction_tests {
 
         let mut flags = 0b00010000;
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
         assert_eq!(target, desired_result);
     }
+
+    fn exec_accumulator(
+        func: fn(Operands, BranchArgs) -> Result<u8, InstructionError>,
+        target: &mut u8,
+        flags: &mut u8,
+    ) {
+        let instruction = Instruction {
+            data: InstructionData::default(),
+            func,
+        };
+        let branch_args = BranchArgs {
+            cycles: vec![4],
+            condition: None,
+        };
+
+        instruction
+            .exec(Operands::One(Word::U8Mut(target), Some(flags)), branch_args)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_daa_after_add() {
+        // 0x19 + 0x28 = 0x41 with half carry, which is 47 in BCD
+        let mut target = 0x41;
+        let mut flags = 0b0010_0000;
+
+        exec_accumulator(daa, &mut target, &mut flags);
+
+        assert_eq!(target, 0x47);
+        assert_eq!(flags, 0);
+    }
+    #[test]
+    fn test_daa_after_add_carry() {
+        // 0x99 + 0x01 = 0x9A, which is 100 in BCD
+        let mut target = 0x9A;
+        let mut flags = 0;
+
+        exec_accumulator(daa, &mut target, &mut flags);
+
+        assert_eq!(target, 0x00);
+        assert_eq!(flags, 0b1001_0000);
+    }
+    #[test]
+    fn test_daa_after_sub() {
+        // 0x42 - 0x15 = 0x2D with half carry, which is 27 in BCD
+        let mut target = 0x2D;
+        let mut flags = 0b0110_0000;
+
+        exec_accumulator(daa, &mut target, &mut flags);
+
+        assert_eq!(target, 0x27);
+        assert_eq!(flags, 0b0100_0000);
+    }
+    #[test]
+    fn test_daa_after_sub_carry() {
+        // 0x10 - 0x20 = 0xF0 with carry, which is 90 in BCD with a borrow
+        let mut target = 0xF0;
+        let mut flags = 0b0101_0000;
+
+        exec_accumulator(daa, &mut target, &mut flags);
+
+        assert_eq!(target, 0x90);
+        assert_eq!(flags, 0b0101_0000);
+    }
+    #[test]
+    fn test_cpl() {
+        let mut target = 0b1010_0101;
+        let mut flags = 0b1001_0000;
+
+        exec_accumulator(cpl, &mut target, &mut flags);
+
+        assert_eq!(target, 0b0101_1010);
+        assert_eq!(flags, 0b1111_0000);
+    }
+    #[test]
+    fn test_scf() {
+        let mut target = 0;
+        let mut flags = 0b1110_0000;
+
+        exec_accumulator(scf, &mut target, &mut flags);
+
+        assert_eq!(flags, 0b1001_0000);
+    }
+    #[test]
+    fn test_ccf() {
+        let mut target = 0;
+        let mut flags = 0b0111_0000;
+
+        exec_accumulator(ccf, &mut target, &mut flags);
+        assert_eq!(flags, 0b0000_0000);
+
+        exec_accumulator(ccf, &mut target, &mut flags);
+        assert_eq!(flags, 0b0001_0000);
+    }
 }
@@ -0,0 +1,456 @@
+use crate::emulator::{bus::Bus, cpu::cpu_registers::CPURegisters};
+
+use super::utils::{Args, BranchArgs, InstructionError, Operands, Ret, Word};
+
+pub fn rlc(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rlc",
+        |value, _| (value.rotate_left(1), value >> 7),
+        true,
+    )
+}
+
+pub fn rrc(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rrc",
+        |value, _| (value.rotate_right(1), value & 0x01),
+        true,
+    )
+}
+
+pub fn rl(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rl",
+        |value, carry| ((value << 1) | carry, value >> 7),
+        true,
+    )
+}
+
+pub fn rr(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rr",
+        |value, carry| ((value >> 1) | (carry << 7), value & 0x01),
+        true,
+    )
+}
+
+pub fn sla(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "sla",
+        |value, _| (value << 1, value >> 7),
+        true,
+    )
+}
+
+pub fn sra(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    // arithmetic shift keeps the sign bit in place
+    shift(
+        operands,
+        branch_args,
+        "sra",
+        |value, _| ((value >> 1) | (value & 0x80), value & 0x01),
+        true,
+    )
+}
+
+pub fn swap(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "swap",
+        |value, _| (value.rotate_left(4), 0),
+        true,
+    )
+}
+
+pub fn srl(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "srl",
+        |value, _| (value >> 1, value & 0x01),
+        true,
+    )
+}
+
+pub fn rlca(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rlca",
+        |value, _| (value.rotate_left(1), value >> 7),
+        false,
+    )
+}
+
+pub fn rrca(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rrca",
+        |value, _| (value.rotate_right(1), value & 0x01),
+        false,
+    )
+}
+
+pub fn rla(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rla",
+        |value, carry| ((value << 1) | carry, value >> 7),
+        false,
+    )
+}
+
+pub fn rra(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    shift(
+        operands,
+        branch_args,
+        "rra",
+        |value, carry| ((value >> 1) | (carry << 7), value & 0x01),
+        false,
+    )
+}
+
+pub fn bit(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    if let Operands::Two(index, source, flags) = operands {
+        match (index, source, flags) {
+            (Word::U8(index), Word::U8(source), Some(flags)) => {
+                let zero = ((source >> index) & 0x01) ^ 0x01;
+
+                // carry flag is left untouched, half carry always set
+                *flags = (zero << 7) | (1 << 5) | (*flags & 0b0001_0000);
+                Ok(branch_args.cycles[0])
+            }
+            (word1, word2, _) => Err(InstructionError::IncorrectOperandsError(format!(
+                "Incorrect words {:?} , {:?} passed to function bit",
+                word1, word2
+            ))),
+        }
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
+pub fn res(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    if let Operands::Two(target, index, _) = operands {
+        match (target, index) {
+            (Word::U8Mut(target), Word::U8(index)) => {
+                *target &= !(1 << index);
+                Ok(branch_args.cycles[0])
+            }
+            (word1, word2) => Err(InstructionError::IncorrectOperandsError(format!(
+                "Incorrect words {:?} , {:?} passed to function res",
+                word1, word2
+            ))),
+        }
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
+pub fn set(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError<'_>> {
+    if let Operands::Two(target, index, _) = operands {
+        match (target, index) {
+            (Word::U8Mut(target), Word::U8(index)) => {
+                *target |= 1 << index;
+                Ok(branch_args.cycles[0])
+            }
+            (word1, word2) => Err(InstructionError::IncorrectOperandsError(format!(
+                "Incorrect words {:?} , {:?} passed to function set",
+                word1, word2
+            ))),
+        }
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
+/// Shared body of the rotate/shift/swap instructions. `op` receives the
+/// operand and the current carry flag (0 or 1) and returns the result along
+/// with the new carry flag. The accumulator rotates always clear Z, so they
+/// pass `set_zero` as false.
+fn shift<'a>(
+    operands: Operands<'a>,
+    branch_args: BranchArgs,
+    name: &str,
+    op: fn(u8, u8) -> (u8, u8),
+    set_zero: bool,
+) -> Result<u8, InstructionError<'a>> {
+    if let Operands::One(target, flags) = operands {
+        match (target, flags) {
+            (Word::U8Mut(target), Some(flags)) => {
+                let carry_in = (*flags & 0b0001_0000) >> 4;
+                let (result, carry) = op(*target, carry_in);
+                *target = result;
+
+                let zero = (set_zero && result == 0) as u8;
+                *flags = (zero << 7) | (carry << 4);
+                Ok(branch_args.cycles[0])
+            }
+            (word1, _) => Err(InstructionError::IncorrectOperandsError(format!(
+                "Incorrect word {:?} passed to function {}",
+                word1, name
+            ))),
+        }
+    } else {
+        Err(InstructionError::InvalidOperandsError(operands))
+    }
+}
+
+/// Operands for the 0xCB prefixed table. `opcode` is the byte following the
+/// prefix: bits 0-2 select the register, bits 3-5 the bit index (or the
+/// rotate/shift kind) and bits 6-7 the instruction group.
+pub fn get_bitops_operands<'a>(
+    registers: &'a mut CPURegisters,
+    mem: &'a mut Bus,
+    opcode: u8,
+    value: Option<Ret>,
+) -> Result<Args<'a>, InstructionError<'a>> {
+    let index = (opcode >> 3) & 0x07;
+    let hl = registers.get_hl();
+
+    // BIT only reads its operand, so hand it a copy rather than a reference
+    if let 0x40..=0x7F = opcode {
+        let source = match opcode & 0x07 {
+            0x0 => registers.b,
+            0x1 => registers.c,
+            0x2 => registers.d,
+            0x3 => registers.e,
+            0x4 => registers.h,
+            0x5 => registers.l,
+            0x6 => mem.read_u8(hl),
+            0x7 => registers.a,
+            _ => return Err(InstructionError::UnimplementedError(opcode)),
+        };
+
+        return Ok((
+            Operands::Two(Word::U8(index), Word::U8(source), Some(&mut registers.f)),
+            None,
+        ));
+    }
+
+    let target = match opcode & 0x07 {
+        0x0 => Word::U8Mut(&mut registers.b),
+        0x1 => Word::U8Mut(&mut registers.c),
+        0x2 => Word::U8Mut(&mut registers.d),
+        0x3 => Word::U8Mut(&mut registers.e),
+        0x4 => Word::U8Mut(&mut registers.h),
+        0x5 => Word::U8Mut(&mut registers.l),
+        0x6 => Word::U8Mut(mem.read_u8_mut(hl)),
+        0x7 => Word::U8Mut(&mut registers.a),
+        _ => return Err(InstructionError::UnimplementedError(opcode)),
+    };
+
+    let ops = match opcode {
+        0x00..=0x3F => Operands::One(target, Some(&mut registers.f)),
+        _ => Operands::Two(target, Word::U8(index), None),
+    };
+
+    Ok((ops, None))
+}
+
+#[cfg(test)]
+mod bitops_instruction_tests {
+    use crate::emulator::instructions::*;
+    use bitops::{bit, res, rl, rla, rlc, rlca, rr, rra, rrc, set, sla, sra, srl, swap};
+    use utils::Word;
+
+    fn exec_one(
+        func: fn(Operands, BranchArgs) -> Result<u8, InstructionError>,
+        target: &mut u8,
+        flags: &mut u8,
+    ) {
+        let instruction = Instruction {
+            data: InstructionData::default(),
+            func,
+        };
+        let branch_args = BranchArgs {
+            cycles: vec![8],
+            condition: None,
+        };
+
+        instruction
+            .exec(Operands::One(Word::U8Mut(target), Some(flags)), branch_args)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_rlc() {
+        let mut target = 0b1000_0001;
+        let mut flags = 0;
+
+        exec_one(rlc, &mut target, &mut flags);
+
+        assert_eq!(target, 0b0000_0011);
+        assert_eq!(flags, 0b0001_0000);
+    }
+    #[test]
+    fn test_rrc() {
+        let mut target = 0b0000_0001;
+        let mut flags = 0;
+
+        exec_one(rrc, &mut target, &mut flags);
+
+        assert_eq!(target, 0b1000_0000);
+        assert_eq!(flags, 0b0001_0000);
+    }
+    #[test]
+    fn test_rl_through_carry() {
+        let mut target = 0b1000_0000;
+        let mut flags = 0b0001_0000;
+
+        exec_one(rl, &mut target, &mut flags);
+
+        assert_eq!(target, 0b0000_0001);
+        assert_eq!(flags, 0b0001_0000);
+    }
+    #[test]
+    fn test_rr_zero_flag() {
+        let mut target = 0b0000_0001;
+        let mut flags = 0;
+
+        exec_one(rr, &mut target, &mut flags);
+
+        assert_eq!(target, 0);
+        assert_eq!(flags, 0b1001_0000);
+    }
+    #[test]
+    fn test_sla() {
+        let mut target = 0b1100_0000;
+        let mut flags = 0b0001_0000;
+
+        exec_one(sla, &mut target, &mut flags);
+
+        assert_eq!(target, 0b1000_0000);
+        assert_eq!(flags, 0b0001_0000);
+    }
+    #[test]
+    fn test_sra_keeps_sign() {
+        let mut target = 0b1000_0010;
+        let mut flags = 0;
+
+        exec_one(sra, &mut target, &mut flags);
+
+        assert_eq!(target, 0b1100_0001);
+        assert_eq!(flags, 0);
+    }
+    #[test]
+    fn test_srl() {
+        let mut target = 0b1000_0011;
+        let mut flags = 0;
+
+        exec_one(srl, &mut target, &mut flags);
+
+        assert_eq!(target, 0b0100_0001);
+        assert_eq!(flags, 0b0001_0000);
+    }
+    #[test]
+    fn test_swap() {
+        let mut target = 0xAB;
+        let mut flags = 0b0111_0000;
+
+        exec_one(swap, &mut target, &mut flags);
+
+        assert_eq!(target, 0xBA);
+        assert_eq!(flags, 0);
+    }
+    #[test]
+    fn test_bit() {
+        let instruction = Instruction {
+            data: InstructionData::default(),
+            func: bit,
+        };
+
+        let mut flags = 0b0101_0000;
+
+        instruction
+            .exec(
+                Operands::Two(Word::U8(3), Word::U8(0b1111_0111), Some(&mut flags)),
+                BranchArgs {
+                    cycles: vec![8],
+                    condition: None,
+                },
+            )
+            .unwrap();
+
+        // zero and half carry set, carry preserved, negative cleared
+        assert_eq!(flags, 0b1011_0000);
+    }
+    #[test]
+    fn test_res_set() {
+        let mut target = 0b0000_0000;
+
+        let instruction = Instruction {
+            data: InstructionData::default(),
+            func: set,
+        };
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(7), None),
+                BranchArgs {
+                    cycles: vec![8],
+                    condition: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(target, 0b1000_0000);
+
+        let instruction = Instruction {
+            data: InstructionData::default(),
+            func: res,
+        };
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(7), None),
+                BranchArgs {
+                    cycles: vec![8],
+                    condition: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(target, 0);
+    }
+
+    #[test]
+    fn test_rlca_clears_zero() {
+        let mut target = 0;
+        let mut flags = 0b1000_0000;
+
+        exec_one(rlca, &mut target, &mut flags);
+
+        assert_eq!(target, 0);
+        assert_eq!(flags, 0);
+    }
+    #[test]
+    fn test_rla() {
+        let mut target = 0b1000_0000;
+        let mut flags = 0;
+
+        exec_one(rla, &mut target, &mut flags);
+
+        assert_eq!(target, 0);
+        assert_eq!(flags, 0b0001_0000);
+    }
+    #[test]
+    fn test_rra() {
+        let mut target = 0b0000_0010;
+        let mut flags = 0b0001_0000;
+
+        exec_one(rra, &mut target, &mut flags);
+
+        assert_eq!(target, 0b1000_0001);
+        assert_eq!(flags, 0);
+    }
+}
@@ -0,0 +1,242 @@
+//! Runs SingleStepTests style JSON vectors through `execute_instruction`.
+//!
+//! Point `SINGLE_STEP_TESTS_DIR` at a checkout of the sm83 vectors (the
+//! directory holding `00.json` ... `ff.json` and `cb 00.json` ... `cb ff.json`).
+//! The test is ignored by default so the suite still runs offline, run it
+//! with `cargo test -- --ignored`.
+
+use std::{
+    fmt, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use super::{execute_instruction, fetch_cb_instructions, fetch_instructions, Instruction};
+use crate::emulator::{bus::Bus, cpu::cpu_registers::CPURegisters};
+
+const TESTS_DIR_VAR: &str = "SINGLE_STEP_TESTS_DIR";
+
+/// Opcodes with no vectors: the prefix itself and the unused slots
+const SKIPPED_OPCODES: [u8; 12] = [
+    0xCB, 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: Snapshot,
+    #[serde(rename = "final")]
+    expected: Snapshot,
+    cycles: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+impl Snapshot {
+    fn registers(&self) -> CPURegisters {
+        CPURegisters {
+            a: self.a,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            f: self.f,
+            h: self.h,
+            l: self.l,
+            sp: self.sp,
+            pc: self.pc,
+        }
+    }
+}
+
+enum Mismatch {
+    Register(&'static str, u16, u16),
+    Flags(u8, u8),
+    Memory(u16, u8, u8),
+    Cycles(u8, u8),
+    Error(String),
+    Panic(String),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Register(name, expected, actual) => write!(
+                f,
+                "register {} expected {:#06x} got {:#06x}",
+                name, expected, actual
+            ),
+            Self::Flags(expected, actual) => {
+                write!(f, "flags expected {:08b} got {:08b}", expected, actual)
+            }
+            Self::Memory(address, expected, actual) => write!(
+                f,
+                "memory {:#06x} expected {:#04x} got {:#04x}",
+                address, expected, actual
+            ),
+            Self::Cycles(expected, actual) => {
+                write!(f, "cycles expected {} got {}", expected, actual)
+            }
+            Self::Error(msg) => write!(f, "returned error: {}", msg),
+            Self::Panic(msg) => write!(f, "panicked: {}", msg),
+        }
+    }
+}
+
+fn run_case(instruction: &Instruction, case: &TestCase) -> Vec<Mismatch> {
+    let mut registers = case.initial.registers();
+    let mut memory = Bus::flat();
+    for &(address, value) in &case.initial.ram {
+        memory.write_u8(address, value);
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        execute_instruction(instruction, &mut registers, &mut memory)
+    }));
+
+    let cycles = match result {
+        Ok(Ok(cycles)) => cycles,
+        Ok(Err(e)) => return vec![Mismatch::Error(e.reason)],
+        Err(e) => {
+            let msg = e
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_default();
+            return vec![Mismatch::Panic(msg)];
+        }
+    };
+
+    let expected = case.expected.registers();
+    let mut mismatches = vec![];
+
+    let pairs: [(&'static str, u16, u16); 9] = [
+        ("A", expected.a as u16, registers.a as u16),
+        ("B", expected.b as u16, registers.b as u16),
+        ("C", expected.c as u16, registers.c as u16),
+        ("D", expected.d as u16, registers.d as u16),
+        ("E", expected.e as u16, registers.e as u16),
+        ("H", expected.h as u16, registers.h as u16),
+        ("L", expected.l as u16, registers.l as u16),
+        ("SP", expected.sp, registers.sp),
+        ("PC", expected.pc, registers.pc),
+    ];
+    for (name, expected, actual) in pairs {
+        if expected != actual {
+            mismatches.push(Mismatch::Register(name, expected, actual));
+        }
+    }
+
+    if expected.f != registers.f {
+        mismatches.push(Mismatch::Flags(expected.f, registers.f));
+    }
+
+    for &(address, value) in &case.expected.ram {
+        let actual = memory.read_u8(address);
+        if actual != value {
+            mismatches.push(Mismatch::Memory(address, value, actual));
+        }
+    }
+
+    // vectors list one entry per M-cycle
+    let expected_cycles = (case.cycles.len() * 4) as u8;
+    if expected_cycles != cycles {
+        mismatches.push(Mismatch::Cycles(expected_cycles, cycles));
+    }
+
+    mismatches
+}
+
+/// Runs every case in `path`, returning how many there were and a summary
+/// line if any of them failed
+fn run_file(path: &Path, instruction: &Instruction) -> (usize, Option<String>) {
+    let json = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Couldn't read {}: {}", path.display(), e));
+    let cases: Vec<TestCase> = serde_json::from_str(&json).expect("Invalid test vector JSON");
+
+    let mut failed = 0;
+    let mut first_failure = None;
+    for case in &cases {
+        let mismatches = run_case(instruction, case);
+        if mismatches.is_empty() {
+            continue;
+        }
+
+        failed += 1;
+        first_failure.get_or_insert_with(|| {
+            let details: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+            format!("{}: {}", case.name, details.join(", "))
+        });
+    }
+
+    let summary = first_failure.map(|first| {
+        format!(
+            "{}: {}/{} cases failed, first - {}",
+            path.file_name().unwrap().to_string_lossy(),
+            failed,
+            cases.len(),
+            first
+        )
+    });
+    (cases.len(), summary)
+}
+
+#[test]
+#[ignore = "needs SingleStepTests vectors"]
+fn test_single_step_vectors() {
+    let dir = std::env::var(TESTS_DIR_VAR)
+        .unwrap_or_else(|_| panic!("{} must be set to run the vectors", TESTS_DIR_VAR));
+    let dir = Path::new(&dir);
+    assert!(
+        dir.is_dir(),
+        "{} is set but {} isn't a directory",
+        TESTS_DIR_VAR,
+        dir.display()
+    );
+
+    let instructions = fetch_instructions();
+    let cb_instructions = fetch_cb_instructions();
+
+    let mut cases = 0;
+    let mut failures = vec![];
+    let mut run = |path: PathBuf, instruction: &Instruction| {
+        let (count, failure) = run_file(&path, instruction);
+        cases += count;
+        failures.extend(failure);
+    };
+    for opcode in 0..=0xFFu8 {
+        if !SKIPPED_OPCODES.contains(&opcode) {
+            run(
+                dir.join(format!("{:02x}.json", opcode)),
+                &instructions[opcode as usize],
+            );
+        }
+        run(
+            dir.join(format!("cb {:02x}.json", opcode)),
+            &cb_instructions[opcode as usize],
+        );
+    }
+
+    assert!(cases > 0, "No test vectors in {}", dir.display());
+    assert!(
+        failures.is_empty(),
+        "{} opcodes failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
@@ -1,10 +1,7 @@
 use num_traits::{ops::overflowing::OverflowingSub, WrappingAdd, WrappingSub};
 
 use super::utils::{Args, BranchArgs, InstructionError, Operands, Ret, Word};
-use crate::emulator::{
-    cpu::cpu_registers::CPURegisters,
-    memory::{Memory, U16Wrapper},
-};
+use crate::emulator::{bus::Bus, cpu::cpu_registers::CPURegisters, memory::U16Wrapper};
 
 pub fn inc(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError> {
     if let Operands::One(target, flags) = operands {
@@ -78,7 +75,7 @@ fn ncrementu8_with_flags(target: u8, increment: bool, flags: &mut u8) -> u8 {
 
 pub fn get_ncrement_operands<'a>(
     registers: &'a mut CPURegisters,
-    mem: &'a mut Memory,
+    mem: &'a mut Bus,
     opcode: u8,
     value: Option<Ret>,
 ) -> Result<Args<'a>, InstructionError<'a>> {
@@ -126,10 +123,12 @@ mod ncrement_instruction_tests {
 
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::One(Word::U8Mut(&mut target), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::One(Word::U8Mut(&mut target), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, desired_value)
     }
@@ -149,10 +148,12 @@ mod ncrement_instruction_tests {
         };
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::One(Word::U8Mut(&mut target), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::One(Word::U8Mut(&mut target), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, desired_value)
     }
@@ -172,10 +173,12 @@ mod ncrement_instruction_tests {
 
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::One(Word::U16Mut(&mut target), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::One(Word::U16Mut(&mut target), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, desired_value)
     }
@@ -195,10 +198,12 @@ mod ncrement_instruction_tests {
 
         let mut flags = 0;
 
-        instruction.exec(
-            Operands::One(Word::U16Mut(&mut target), Some(&mut flags)),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::One(Word::U16Mut(&mut target), Some(&mut flags)),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, desired_value)
     }
@@ -4,10 +4,7 @@ use num_traits::{
 };
 
 use super::utils::{check_condition, Args, BranchArgs, InstructionError, Operands, Ret, Word};
-use crate::emulator::{
-    cpu::cpu_registers::CPURegisters,
-    memory::{Memory, U16Wrapper},
-};
+use crate::emulator::{bus::Bus, cpu::cpu_registers::CPURegisters, memory::U16Wrapper};
 
 pub fn jp(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError> {
     if let Operands::Two(target, source, flags) = operands {
@@ -72,7 +69,7 @@ pub fn jr(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instruc
 
 pub fn get_jump_operands<'a>(
     registers: &'a mut CPURegisters,
-    mem: &'a mut Memory,
+    mem: &'a mut Bus,
     opcode: u8,
     value: Option<Ret>,
 ) -> Result<Args<'a>, InstructionError<'a>> {
@@ -235,14 +232,16 @@ mod jump_instruction_tests {
         let target_instruction = 0xAAAA;
         let mut fake_pc: u16 = 0x0000;
 
-        instruction.exec(
-            Operands::Two(
-                Word::U16Mut(&mut fake_pc),
-                Word::U16(target_instruction),
-                Some(&mut flags),
-            ),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(
+                    Word::U16Mut(&mut fake_pc),
+                    Word::U16(target_instruction),
+                    Some(&mut flags),
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(fake_pc, target_instruction);
     }
@@ -264,14 +263,16 @@ mod jump_instruction_tests {
         let target_instruction = 0xAAAA;
         let mut fake_pc: u16 = 0x0000;
 
-        let cycles = instruction.exec(
-            Operands::Two(
-                Word::U16Mut(&mut fake_pc),
-                Word::U16(target_instruction),
-                Some(&mut flags),
-            ),
-            branch_args,
-        );
+        let cycles = instruction
+            .exec(
+                Operands::Two(
+                    Word::U16Mut(&mut fake_pc),
+                    Word::U16(target_instruction),
+                    Some(&mut flags),
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(fake_pc, target_instruction);
         assert_eq!(cycles, 16);
@@ -294,14 +295,16 @@ mod jump_instruction_tests {
         let target_instruction = 0xAAAA;
         let mut fake_pc: u16 = 0x0000;
 
-        let cycles = instruction.exec(
-            Operands::Two(
-                Word::U16Mut(&mut fake_pc),
-                Word::U16(target_instruction),
-                Some(&mut flags),
-            ),
-            branch_args,
-        );
+        let cycles = instruction
+            .exec(
+                Operands::Two(
+                    Word::U16Mut(&mut fake_pc),
+                    Word::U16(target_instruction),
+                    Some(&mut flags),
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(fake_pc, 0x0000);
         assert_eq!(cycles, 12);
@@ -324,14 +327,16 @@ mod jump_instruction_tests {
         let target_instruction: i8 = -120;
         let mut fake_pc: u16 = pc_value;
 
-        let cycles = instruction.exec(
-            Operands::Two(
-                Word::U16Mut(&mut fake_pc),
-                Word::U8(target_instruction as u8),
-                Some(&mut flags),
-            ),
-            branch_args,
-        );
+        let cycles = instruction
+            .exec(
+                Operands::Two(
+                    Word::U16Mut(&mut fake_pc),
+                    Word::U8(target_instruction as u8),
+                    Some(&mut flags),
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(
             fake_pc,
@@ -1,14 +1,27 @@
 use num_traits::WrappingAdd;
 
-use crate::emulator::{
-    cpu::cpu_registers::CPURegisters,
-    memory::{Memory, U16Wrapper},
-};
+use crate::emulator::{bus::Bus, cpu::cpu_registers::CPURegisters, memory::U16Wrapper};
 
-use super::utils::{Args, BranchArgs, InstructionError, Operands, Ret, Word};
+use super::{
+    arithmetic::add_sp_offset_with_flags,
+    utils::{Args, BranchArgs, InstructionError, Operands, Ret, Word},
+};
 
 pub fn ld(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError> {
-    if let Operands::Two(target, source, _) = operands {
+    // LD HL, SP + e8 is the only load that sets flags
+    if let Operands::Three(target, base, offset, flags) = operands {
+        match (target, base, offset, flags) {
+            (Word::U16WrapperMut(target), Word::U16(base), Word::U8(offset), Some(flags)) => {
+                target.from_u16(add_sp_offset_with_flags(base, offset, flags));
+            }
+            (word1, word2, word3, _) => {
+                return Err(InstructionError::IncorrectOperandsError(format!(
+                    "Incorrect words {:?} , {:?}, {:?} passed to load function ld",
+                    word1, word2, word3
+                )));
+            }
+        }
+    } else if let Operands::Two(target, source, _) = operands {
         match (target, source) {
             (Word::U8Mut(target), Word::U8(source)) => {
                 *target = source;
@@ -30,7 +43,7 @@ pub fn ld(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instruc
 
 pub fn get_ld_operands<'a>(
     registers: &'a mut CPURegisters,
-    mem: &'a mut Memory,
+    mem: &'a mut Bus,
     opcode: u8,
     value: Option<Ret>,
 ) -> Result<Args<'a>, InstructionError<'a>> {
@@ -95,16 +108,19 @@ pub fn get_ld_operands<'a>(
                 } else {
                     return Err(InstructionError::InvalidLiteral(value.unwrap()));
                 };
+                let address = 0xFF00 | value as u16;
 
                 match hi {
+                    // LDH [a8], A
                     0xE => Operands::Two(
-                        Word::U8Mut(mem.read_u8_mut(value as u16)),
+                        Word::U8Mut(mem.read_u8_mut(address)),
                         Word::U8(registers.a),
                         None,
                     ),
+                    // LDH A, [a8]
                     0xF => Operands::Two(
                         Word::U8Mut(&mut registers.a),
-                        Word::U8(mem.read_u8(value as u16)),
+                        Word::U8(mem.read_u8(address)),
                         None,
                     ),
                     _ => return Err(InstructionError::UnimplementedError(opcode)),
@@ -151,55 +167,76 @@ pub fn get_ld_operands<'a>(
                         Word::U8(source),
                         None,
                     ),
+                    // LD [HL+], A
                     0x2 => {
                         let hl = registers.get_hl();
-                        registers.set_hl(hl + 1);
+                        registers.set_hl(hl.wrapping_add(1));
                         Operands::Two(Word::U8Mut(mem.read_u8_mut(hl)), Word::U8(source), None)
                     }
+                    // LD [HL-], A
                     0x3 => {
                         let hl = registers.get_hl();
-                        registers.set_hl(hl - 1);
+                        registers.set_hl(hl.wrapping_sub(1));
                         Operands::Two(Word::U8Mut(mem.read_u8_mut(hl)), Word::U8(source), None)
                     }
+                    // LD [C], A
                     0xE => Operands::Two(
-                        Word::U8Mut(mem.read_u8_mut(registers.c as u16)),
+                        Word::U8Mut(mem.read_u8_mut(0xFF00 | registers.c as u16)),
                         Word::U8(source),
                         None,
                     ),
+                    // LD A, [C]
                     0xF => Operands::Two(
-                        Word::U8Mut(mem.read_u8_mut(source as u16)),
-                        Word::U8(registers.c),
+                        Word::U8Mut(&mut registers.a),
+                        Word::U8(mem.read_u8(0xFF00 | reg_copy.c as u16)),
                         None,
                     ),
                     _ => return Err(InstructionError::UnimplementedError(opcode)),
                 }
             }
-            0x8 => {
-                let mut u16_value: u16 = 0;
-                let mut u8_value: u8 = 0;
+            // LD r8, n8
+            0x6 | 0xE => {
+                let value = if let Some(Ret::U8(x)) = value {
+                    x
+                } else {
+                    return Err(InstructionError::InvalidLiteral(value.unwrap()));
+                };
 
-                match value {
-                    Some(Ret::U16(x)) => {
-                        u16_value = x;
-                    }
-                    Some(Ret::U8(x)) => u8_value = x,
-                    _ => return Err(InstructionError::InvalidLiteral(value.unwrap())),
-                }
+                let dest = match opcode {
+                    0x06 => Word::U8Mut(&mut registers.b),
+                    0x0E => Word::U8Mut(&mut registers.c),
+                    0x16 => Word::U8Mut(&mut registers.d),
+                    0x1E => Word::U8Mut(&mut registers.e),
+                    0x26 => Word::U8Mut(&mut registers.h),
+                    0x2E => Word::U8Mut(&mut registers.l),
+                    0x36 => Word::U8Mut(mem.read_u8_mut(reg_copy.get_hl())),
+                    0x3E => Word::U8Mut(&mut registers.a),
+                    _ => return Err(InstructionError::UnimplementedError(opcode)),
+                };
 
-                match hi {
-                    0x0 => Operands::Two(
-                        Word::U16WrapperMut(U16Wrapper(&mut registers.b, &mut registers.c)),
-                        Word::U16(u16_value),
-                        None,
-                    ),
-                    0xF => Operands::Two(
-                        Word::U16WrapperMut(U16Wrapper(&mut registers.h, &mut registers.l)),
-                        Word::U16(registers.sp.wrapping_add(u8_value as u16)),
+                Operands::Two(dest, Word::U8(value), None)
+            }
+            0x8 => match (hi, value) {
+                // LD [a16], SP
+                (0x0, Some(Ret::U16(address))) => {
+                    // memory is little endian, so the low byte of SP goes first
+                    let U16Wrapper(low, high) = mem.read_u16wrapper(address);
+                    Operands::Two(
+                        Word::U16WrapperMut(U16Wrapper(high, low)),
+                        Word::U16(registers.sp),
                         None,
-                    ),
-                    _ => return Err(InstructionError::UnimplementedError(opcode)),
+                    )
                 }
-            }
+                // LD HL, SP + e8
+                (0xF, Some(Ret::U8(offset))) => Operands::Three(
+                    Word::U16WrapperMut(U16Wrapper(&mut registers.h, &mut registers.l)),
+                    Word::U16(reg_copy.sp),
+                    Word::U8(offset),
+                    Some(&mut registers.f),
+                ),
+                (0x0 | 0xF, value) => return Err(InstructionError::InvalidLiteral(value.unwrap())),
+                _ => return Err(InstructionError::UnimplementedError(opcode)),
+            },
             0x9 => match hi {
                 0xF => {
                     let hl = reg_copy.get_hl();
@@ -218,18 +255,20 @@ pub fn get_ld_operands<'a>(
                     Word::U8(mem.read_u8(reg_copy.get_de())),
                     None,
                 ),
+                // LD A, [HL+]
                 0x2 => {
                     let hl = reg_copy.get_hl();
-                    registers.set_hl(hl + 1);
+                    registers.set_hl(hl.wrapping_add(1));
                     Operands::Two(
                         Word::U8Mut(&mut registers.a),
                         Word::U8(mem.read_u8(hl)),
                         None,
                     )
                 }
+                // LD A, [HL-]
                 0x3 => {
-                    let hl = registers.get_hl();
-                    registers.set_hl(hl + 1);
+                    let hl = reg_copy.get_hl();
+                    registers.set_hl(hl.wrapping_sub(1));
                     Operands::Two(
                         Word::U8Mut(&mut registers.a),
                         Word::U8(mem.read_u8(hl)),
@@ -264,38 +303,6 @@ pub fn get_ld_operands<'a>(
                 }
                 _ => return Err(InstructionError::UnimplementedError(opcode)),
             },
-
-            0xE => match hi {
-                0x0 => Operands::Two(
-                    Word::U8Mut(&mut registers.c),
-                    Word::U8(mem.read_u8(reg_copy.get_bc())),
-                    None,
-                ),
-                0x1 => Operands::Two(
-                    Word::U8Mut(&mut registers.e),
-                    Word::U8(mem.read_u8(reg_copy.get_de())),
-                    None,
-                ),
-                0x2 => {
-                    let hl = reg_copy.get_hl();
-                    registers.set_hl(hl + 1);
-                    Operands::Two(
-                        Word::U8Mut(&mut registers.l),
-                        Word::U8(mem.read_u8(hl)),
-                        None,
-                    )
-                }
-                0x3 => {
-                    let hl = registers.get_hl();
-                    registers.set_hl(hl + 1);
-                    Operands::Two(
-                        Word::U8Mut(&mut registers.a),
-                        Word::U8(mem.read_u8(hl)),
-                        None,
-                    )
-                }
-                _ => return Err(InstructionError::UnimplementedError(opcode)),
-            },
             _ => return Err(InstructionError::UnimplementedError(opcode)),
         };
         Ok((ops, None))
@@ -323,10 +330,12 @@ mod load_instruction_tests {
             condition: None,
         };
 
-        instruction.exec(
-            Operands::Two(Word::U8Mut(&mut target), Word::U8(source), None),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(Word::U8Mut(&mut target), Word::U8(source), None),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, source)
     }
@@ -345,14 +354,16 @@ mod load_instruction_tests {
             condition: None,
         };
 
-        instruction.exec(
-            Operands::Two(
-                Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
-                Word::U16(source),
-                None,
-            ),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(
+                    Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
+                    Word::U16(source),
+                    None,
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         let expected_values = convert_u16_to_two_u8s(source);
         assert_eq!(target, expected_values)
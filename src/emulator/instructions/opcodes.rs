@@ -0,0 +1,5 @@
+//! Opcode tables generated by `build.rs` from `instructions.json`
+
+use super::utils::{InstructionData, OpKind};
+
+include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
@@ -5,8 +5,9 @@ use num_traits::{
 
 use super::utils::{check_condition, Args, BranchArgs, InstructionError, Operands, Ret, Word};
 use crate::emulator::{
+    bus::Bus,
     cpu::cpu_registers::{convert_u16_to_two_u8s, CPURegisters},
-    memory::{Memory, U16Wrapper},
+    memory::U16Wrapper,
 };
 
 pub fn push_pop(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, InstructionError> {
@@ -37,7 +38,7 @@ pub fn ret(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instru
                     if check_condition(*flags.unwrap(), condition) {
                         let new_source = U16Wrapper(stack.1, stack.0);
                         *pc = new_source.into_u16();
-                        *sp += 2;
+                        *sp = sp.wrapping_add(2);
                         Ok(branch_args.cycles[0])
                     } else {
                         Ok(branch_args.cycles[1])
@@ -45,7 +46,7 @@ pub fn ret(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instru
                 } else {
                     let new_source = U16Wrapper(stack.1, stack.0);
                     *pc = new_source.into_u16();
-                    *sp += 2;
+                    *sp = sp.wrapping_add(2);
                     Ok(branch_args.cycles[0])
                 }
             }
@@ -75,7 +76,7 @@ pub fn call(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instr
                         let split_pc = convert_u16_to_two_u8s(*pc);
                         *stack.0 = split_pc.1;
                         *stack.1 = split_pc.0;
-                        *sp -= 2;
+                        *sp = sp.wrapping_sub(2);
                         *pc = address;
                         Ok(branch_args.cycles[0])
                     } else {
@@ -85,7 +86,7 @@ pub fn call(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instr
                     let split_pc = convert_u16_to_two_u8s(*pc);
                     *stack.0 = split_pc.1;
                     *stack.1 = split_pc.0;
-                    *sp -= 2;
+                    *sp = sp.wrapping_sub(2);
                     *pc = address;
                     Ok(branch_args.cycles[0])
                 }
@@ -104,7 +105,7 @@ pub fn call(operands: Operands<'_>, branch_args: BranchArgs) -> Result<u8, Instr
 
 pub fn get_stack_operands<'a>(
     registers: &'a mut CPURegisters,
-    mem: &'a mut Memory,
+    mem: &'a mut Bus,
     opcode: u8,
     value: Option<Ret>,
 ) -> Result<Args<'a>, InstructionError<'a>> {
@@ -122,7 +123,7 @@ pub fn get_stack_operands<'a>(
     let ops = match lo {
         // push
         0x5 => {
-            registers.sp -= 2;
+            registers.sp = registers.sp.wrapping_sub(2);
             Operands::Two(
                 Word::U16WrapperMut(mem.read_u16wrapper(registers.sp)),
                 Word::U16WrapperMut(source),
@@ -133,10 +134,10 @@ pub fn get_stack_operands<'a>(
         0x1 => {
             let ops = Operands::Two(
                 Word::U16WrapperMut(source),
-                Word::U16WrapperMut(mem.read_u16wrapper(registers.sp)),
+                Word::U16WrapperMut(mem.peek_u16wrapper(registers.sp)),
                 None,
             );
-            registers.sp += 2;
+            registers.sp = registers.sp.wrapping_add(2);
             ops
         }
         _ => return Err(InstructionError::UnimplementedError(opcode)),
@@ -146,13 +147,13 @@ pub fn get_stack_operands<'a>(
 
 pub fn get_ret_operands<'a>(
     registers: &'a mut CPURegisters,
-    mem: &'a mut Memory,
+    mem: &'a mut Bus,
     opcode: u8,
     value: Option<Ret>,
 ) -> Result<Args<'a>, InstructionError<'a>> {
     let ops = Operands::Ret(
         Word::U16Mut(&mut registers.pc),
-        Word::U16WrapperMut(mem.read_u16wrapper(registers.sp)),
+        Word::U16WrapperMut(mem.peek_u16wrapper(registers.sp)),
         Word::U16Mut(&mut registers.sp),
         Some(&mut registers.f),
     );
@@ -169,7 +170,7 @@ pub fn get_ret_operands<'a>(
 }
 pub fn get_call_operands<'a>(
     registers: &'a mut CPURegisters,
-    mem: &'a mut Memory,
+    mem: &'a mut Bus,
     opcode: u8,
     value: Option<Ret>,
 ) -> Result<Args<'a>, InstructionError<'a>> {
@@ -180,7 +181,7 @@ pub fn get_call_operands<'a>(
     };
 
     let ops = Operands::Call(
-        Word::U16WrapperMut(mem.read_u16wrapper(registers.sp - 2)),
+        Word::U16WrapperMut(mem.read_u16wrapper(registers.sp.wrapping_sub(2))),
         Word::U16Mut(&mut registers.pc),
         Word::U16Mut(&mut registers.sp),
         Word::U16(address), // jump here
@@ -197,6 +198,28 @@ pub fn get_call_operands<'a>(
     Ok((ops, condition))
 }
 
+/// RST is a one byte CALL to one of eight fixed vectors, encoded in bits 3-5.
+pub fn get_rst_operands<'a>(
+    registers: &'a mut CPURegisters,
+    mem: &'a mut Bus,
+    opcode: u8,
+    value: Option<Ret>,
+) -> Result<Args<'a>, InstructionError<'a>> {
+    let vector = match opcode {
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => (opcode & 0x38) as u16,
+        _ => return Err(InstructionError::UnimplementedError(opcode)),
+    };
+
+    let ops = Operands::Call(
+        Word::U16WrapperMut(mem.read_u16wrapper(registers.sp.wrapping_sub(2))),
+        Word::U16Mut(&mut registers.pc),
+        Word::U16Mut(&mut registers.sp),
+        Word::U16(vector),
+        None,
+    );
+    Ok((ops, None))
+}
+
 #[cfg(test)]
 mod stack_instruction_tests {
     use crate::emulator::{
@@ -221,14 +244,16 @@ mod stack_instruction_tests {
             condition: None,
         };
 
-        instruction.exec(
-            Operands::Two(
-                Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
-                Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
-                None,
-            ),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Two(
+                    Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
+                    Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
+                    None,
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, (expected_values.1, expected_values.0))
     }
@@ -249,15 +274,17 @@ mod stack_instruction_tests {
 
         let mut stack_pointer = 0;
 
-        instruction.exec(
-            Operands::Ret(
-                Word::U16Mut(&mut target),
-                Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
-                Word::U16Mut(&mut stack_pointer),
-                None,
-            ),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Ret(
+                    Word::U16Mut(&mut target),
+                    Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
+                    Word::U16Mut(&mut stack_pointer),
+                    None,
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(
             target,
@@ -286,15 +313,17 @@ mod stack_instruction_tests {
 
         let mut stack_pointer = 0;
 
-        let result_cycles = instruction.exec(
-            Operands::Ret(
-                Word::U16Mut(&mut target),
-                Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
-                Word::U16Mut(&mut stack_pointer),
-                Some(&mut flags), // condition is same as flags
-            ),
-            branch_args,
-        );
+        let result_cycles = instruction
+            .exec(
+                Operands::Ret(
+                    Word::U16Mut(&mut target),
+                    Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
+                    Word::U16Mut(&mut stack_pointer),
+                    Some(&mut flags), // condition is same as flags
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(
             target,
@@ -325,15 +354,17 @@ mod stack_instruction_tests {
 
         let mut stack_pointer = 0;
 
-        let result_cycles = instruction.exec(
-            Operands::Ret(
-                Word::U16Mut(&mut target),
-                Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
-                Word::U16Mut(&mut stack_pointer),
-                Some(&mut flags), // condition is same as flags
-            ),
-            branch_args,
-        );
+        let result_cycles = instruction
+            .exec(
+                Operands::Ret(
+                    Word::U16Mut(&mut target),
+                    Word::U16WrapperMut(U16Wrapper(&mut expected_values.0, &mut expected_values.1)),
+                    Word::U16Mut(&mut stack_pointer),
+                    Some(&mut flags), // condition is same as flags
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(target, answer);
 
@@ -358,16 +389,18 @@ mod stack_instruction_tests {
 
         let mut stack_pointer = 10;
 
-        instruction.exec(
-            Operands::Call(
-                Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
-                Word::U16Mut(&mut pc),
-                Word::U16Mut(&mut stack_pointer),
-                Word::U16(address),
-                None,
-            ),
-            branch_args,
-        );
+        instruction
+            .exec(
+                Operands::Call(
+                    Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
+                    Word::U16Mut(&mut pc),
+                    Word::U16Mut(&mut stack_pointer),
+                    Word::U16(address),
+                    None,
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(pc, address);
         assert_eq!(target, (0x96, 0x69));
@@ -393,16 +426,18 @@ mod stack_instruction_tests {
 
         let mut stack_pointer = 10;
 
-        let result_cycles = instruction.exec(
-            Operands::Call(
-                Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
-                Word::U16Mut(&mut pc),
-                Word::U16Mut(&mut stack_pointer),
-                Word::U16(address),
-                Some(&mut flags),
-            ),
-            branch_args,
-        );
+        let result_cycles = instruction
+            .exec(
+                Operands::Call(
+                    Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
+                    Word::U16Mut(&mut pc),
+                    Word::U16Mut(&mut stack_pointer),
+                    Word::U16(address),
+                    Some(&mut flags),
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(pc, address);
         assert_eq!(target, (0x96, 0x69));
@@ -430,16 +465,18 @@ mod stack_instruction_tests {
 
         let mut stack_pointer = 10;
 
-        let result_cycles = instruction.exec(
-            Operands::Call(
-                Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
-                Word::U16Mut(&mut pc),
-                Word::U16Mut(&mut stack_pointer),
-                Word::U16(address),
-                Some(&mut flags),
-            ),
-            branch_args,
-        );
+        let result_cycles = instruction
+            .exec(
+                Operands::Call(
+                    Word::U16WrapperMut(U16Wrapper(&mut target.0, &mut target.1)),
+                    Word::U16Mut(&mut pc),
+                    Word::U16Mut(&mut stack_pointer),
+                    Word::U16(address),
+                    Some(&mut flags),
+                ),
+                branch_args,
+            )
+            .unwrap();
 
         assert_eq!(pc, start_pc);
         assert_eq!(target, convert_u16_to_two_u8s(source));
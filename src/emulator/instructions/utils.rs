@@ -1,9 +1,7 @@
 use core::fmt;
 use std::error::Error;
 
-use serde::{Deserialize, Serialize};
-
-use crate::emulator::memory::U16Wrapper;
+use crate::emulator::{cpu::cpu_registers::CPURegisters, memory::U16Wrapper};
 pub type Args<'a> = (Operands<'a>, Option<u8>);
 
 #[derive(Debug)]
@@ -11,6 +9,7 @@ pub enum Operands<'a> {
     None,
     One(Word<'a>, Option<&'a mut u8>),
     Two(Word<'a>, Word<'a>, Option<&'a mut u8>),
+    Three(Word<'a>, Word<'a>, Word<'a>, Option<&'a mut u8>), // Special case for LD HL, SP + e8
     Ret(Word<'a>, Word<'a>, Word<'a>, Option<&'a mut u8>), // Special case for RET instruction
     Call(Word<'a>, Word<'a>, Word<'a>, Word<'a>, Option<&'a mut u8>), // Special case for CALL instruction
 }
@@ -31,29 +30,75 @@ pub enum Ret {
     U16(u16),
 }
 
-#[derive(Default, Debug, Deserialize, Serialize, Clone)]
+/// Decoded form of an opcode, generated at build time from `instructions.json`
+#[derive(Debug, Clone, Copy)]
 pub struct InstructionData {
-    pub mnemonic: String,
+    pub kind: OpKind,
     pub bytes: u8,
-    pub cycles: Vec<u8>,
-    pub immediate: bool,
-    pub flags: InstructionDataFlags,
+    /// Taken and not taken cycle counts for conditional instructions
+    pub cycles: &'static [u8],
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
-pub struct InstructionDataOperands {
-    pub name: String,
-    pub immediate: bool,
-    pub bytes: Option<u8>,
+impl Default for InstructionData {
+    fn default() -> Self {
+        Self {
+            kind: OpKind::Nop,
+            bytes: 1,
+            cycles: &[4],
+        }
+    }
 }
 
-#[allow(non_snake_case)]
-#[derive(Default, Debug, Deserialize, Serialize, Clone)]
-pub struct InstructionDataFlags {
-    pub Z: String,
-    pub N: String,
-    pub H: String,
-    pub C: String,
+/// One variant per mnemonic in `instructions.json`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpKind {
+    Nop,
+    Ld,
+    Ldh,
+    Inc,
+    Dec,
+    Add,
+    Adc,
+    Sub,
+    Sbc,
+    And,
+    Xor,
+    Or,
+    Cp,
+    Jp,
+    Jr,
+    Call,
+    Ret,
+    Reti,
+    Rst,
+    Push,
+    Pop,
+    Rlca,
+    Rrca,
+    Rla,
+    Rra,
+    Daa,
+    Cpl,
+    Scf,
+    Ccf,
+    Halt,
+    Stop,
+    Di,
+    Ei,
+    Prefix,
+    Illegal,
+    // 0xCB prefixed
+    Rlc,
+    Rrc,
+    Rl,
+    Rr,
+    Sla,
+    Sra,
+    Swap,
+    Srl,
+    Bit,
+    Res,
+    Set,
 }
 
 #[derive(Debug)]
@@ -97,6 +142,41 @@ impl<'a> fmt::Display for InstructionError<'a> {
 
 impl<'a> Error for InstructionError<'a> {}
 
+/// Owned record of an instruction that failed, including a snapshot of the
+/// registers from before it was fetched so it can outlive the CPU state it
+/// came from.
+#[derive(Debug, Clone)]
+pub struct ExecutionError {
+    /// 0xCBxx for prefixed instructions
+    pub opcode: u16,
+    pub pc: u16,
+    pub registers: CPURegisters,
+    pub reason: String,
+}
+
+impl ExecutionError {
+    pub fn new(opcode: u16, pc: u16, registers: &CPURegisters, reason: String) -> Self {
+        Self {
+            opcode,
+            pc,
+            registers: registers.clone(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Opcode {:#04x} at PC {:#06x} failed: {}\n\tRegisters - {:?}",
+            self.opcode, self.pc, self.reason, self.registers
+        )
+    }
+}
+
+impl Error for ExecutionError {}
+
 pub fn check_condition(flags: u8, condition: u8) -> bool {
     (flags & condition) == condition
 }
@@ -0,0 +1,121 @@
+pub const IE_ADDRESS: u16 = 0xFFFF;
+pub const IF_ADDRESS: u16 = 0xFF0F;
+
+/// T-cycles taken to push PC and jump to an interrupt vector (5 M-cycles)
+pub const DISPATCH_CYCLES: u8 = 20;
+
+/// Interrupt sources, declared in priority order (VBlank is serviced first).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank = 0,
+    LcdStat = 1,
+    Timer = 2,
+    Serial = 3,
+    Joypad = 4,
+}
+
+impl Interrupt {
+    const ALL: [Interrupt; 5] = [
+        Interrupt::VBlank,
+        Interrupt::LcdStat,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn vector(self) -> u16 {
+        0x40 + 8 * self as u16
+    }
+}
+
+/// Owner of the IE (0xFFFF) and IF (0xFF0F) registers. Peripherals raise
+/// interrupts through `request` and the CPU services them while IME is set.
+#[derive(Default, Debug)]
+pub struct Interrupts {
+    pub enable: u8,
+    pub flag: u8,
+}
+
+impl Interrupts {
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.flag |= interrupt.mask();
+    }
+
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.flag &= !interrupt.mask();
+    }
+
+    /// Interrupts that are both enabled and requested
+    pub fn pending(&self) -> u8 {
+        self.enable & self.flag & 0x1F
+    }
+
+    /// Highest priority pending interrupt, if any
+    pub fn next(&self) -> Option<Interrupt> {
+        let pending = self.pending();
+        Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            IE_ADDRESS => self.enable,
+            // only the low 5 bits of IF are wired, the rest read back as 1
+            IF_ADDRESS => self.flag | 0xE0,
+            _ => panic!("Address {:#06x} is not an interrupt register", address),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            IE_ADDRESS => self.enable = value,
+            IF_ADDRESS => self.flag = value & 0x1F,
+            _ => panic!("Address {:#06x} is not an interrupt register", address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod interrupt_tests {
+    use super::{Interrupt, Interrupts, IE_ADDRESS, IF_ADDRESS};
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(Interrupt::VBlank.vector(), 0x40);
+        assert_eq!(Interrupt::LcdStat.vector(), 0x48);
+        assert_eq!(Interrupt::Timer.vector(), 0x50);
+        assert_eq!(Interrupt::Serial.vector(), 0x58);
+        assert_eq!(Interrupt::Joypad.vector(), 0x60);
+    }
+
+    #[test]
+    fn test_next_respects_enable_and_priority() {
+        let mut interrupts = Interrupts::default();
+        interrupts.request(Interrupt::Joypad);
+        interrupts.request(Interrupt::Timer);
+
+        assert_eq!(interrupts.next(), None);
+
+        interrupts.enable = Interrupt::Joypad.mask() | Interrupt::Timer.mask();
+        assert_eq!(interrupts.next(), Some(Interrupt::Timer));
+
+        interrupts.acknowledge(Interrupt::Timer);
+        assert_eq!(interrupts.next(), Some(Interrupt::Joypad));
+    }
+
+    #[test]
+    fn test_register_reads() {
+        let mut interrupts = Interrupts::default();
+        interrupts.write(IF_ADDRESS, 0xFF);
+        interrupts.write(IE_ADDRESS, 0xFF);
+
+        assert_eq!(interrupts.flag, 0x1F);
+        assert_eq!(interrupts.read(IF_ADDRESS), 0xFF);
+        assert_eq!(interrupts.read(IE_ADDRESS), 0xFF);
+    }
+}
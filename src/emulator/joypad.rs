@@ -0,0 +1,157 @@
+use super::interrupts::Interrupt;
+
+pub const JOYPAD_ADDRESS: u16 = 0xFF00;
+
+/// P1 bits 4 and 5 pick the rows, a row is selected when its bit is low
+const SELECT_DIRECTIONS: u8 = 0x10;
+const SELECT_ACTIONS: u8 = 0x20;
+const SELECT_MASK: u8 = SELECT_DIRECTIONS | SELECT_ACTIONS;
+/// P1 bits 6 and 7 aren't wired and read back as 1
+const P1_UNUSED: u8 = 0xC0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// Whether the button sits in the direction row, and its line in P1
+    fn line(self) -> (bool, u8) {
+        match self {
+            Button::Right => (true, 0x01),
+            Button::Left => (true, 0x02),
+            Button::Up => (true, 0x04),
+            Button::Down => (true, 0x08),
+            Button::A => (false, 0x01),
+            Button::B => (false, 0x02),
+            Button::Select => (false, 0x04),
+            Button::Start => (false, 0x08),
+        }
+    }
+}
+
+/// P1 (0xFF00). Buttons pull their line low while the row they're wired to
+/// is selected, and any line going from high to low requests the joypad
+/// interrupt.
+#[derive(Debug, Default)]
+pub struct Joypad {
+    /// P1 bits 4 and 5 as last written
+    select: u8,
+    /// Held buttons as P1 lines, set while pressed
+    directions: u8,
+    actions: u8,
+    /// Interrupts raised since the bus last collected them
+    requested: u8,
+}
+
+impl Joypad {
+    /// Interrupts raised since the last call, as IF bits
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.requested)
+    }
+
+    pub fn read(&self) -> u8 {
+        P1_UNUSED | self.select | self.lines()
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.update(|joypad| joypad.select = value & SELECT_MASK);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.update(|joypad| match button.line() {
+            (true, line) => joypad.directions |= line,
+            (false, line) => joypad.actions |= line,
+        });
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.update(|joypad| match button.line() {
+            (true, line) => joypad.directions &= !line,
+            (false, line) => joypad.actions &= !line,
+        });
+    }
+
+    /// Applies `change`, requesting the interrupt if it pulled a line low
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        let before = self.lines();
+        change(self);
+        if before & !self.lines() != 0 {
+            self.requested |= Interrupt::Joypad.mask();
+        }
+    }
+
+    /// P10-P13, low for held buttons in the selected rows
+    fn lines(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= self.directions;
+        }
+        if self.select & SELECT_ACTIONS == 0 {
+            pressed |= self.actions;
+        }
+        !pressed & 0x0F
+    }
+}
+
+#[cfg(test)]
+mod joypad_tests {
+    use super::{Button, Joypad};
+
+    #[test]
+    fn test_rows() {
+        let mut joypad = Joypad::default();
+        joypad.press(Button::Down);
+        joypad.press(Button::A);
+
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0xE7);
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0xDE);
+        joypad.write(0x30);
+        assert_eq!(joypad.read(), 0xFF);
+        // both rows at once
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xC6);
+
+        joypad.release(Button::Down);
+        assert_eq!(joypad.read(), 0xCE);
+    }
+
+    #[test]
+    fn test_interrupt_on_press() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x10);
+
+        joypad.press(Button::Start);
+        assert_eq!(joypad.take_interrupts(), 0x10);
+
+        // the line is already low, and releases never count
+        joypad.press(Button::Start);
+        joypad.release(Button::Start);
+        assert_eq!(joypad.take_interrupts(), 0);
+
+        joypad.press(Button::Select);
+        assert_eq!(joypad.take_interrupts(), 0x10);
+    }
+
+    #[test]
+    fn test_unselected_row_stays_quiet() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x20);
+
+        joypad.press(Button::Start);
+        assert_eq!(joypad.take_interrupts(), 0);
+
+        // selecting the row pulls the line low
+        joypad.write(0x10);
+        assert_eq!(joypad.take_interrupts(), 0x10);
+    }
+}
@@ -1,50 +1,6 @@
 #![allow(unused)]
-
-#[derive(Default)]
-
-pub struct Memory {
-    buf: Buffer<0xFFFF>,
-}
-
-pub enum Partitions {
-    Rom0 = 0x0000
-}
-
-impl Memory {
-    pub fn new() -> Self {
-        Self::default()
-    }
-
-    pub fn read_u8_mut(&mut self, address: u16) -> &mut u8 {
-        self.buf.read_u8_mut(address)
-    }
-    pub fn read_u8(&self, address: u16) -> u8 {
-        self.buf.read_u8(address)
-    }
-
-    pub fn read_u16(&self, address: u16) -> u16 {
-        (self.read_u8(address) as u16) | ((self.read_u8(address + 1) as u16) << 8)
-    }
-
-    pub fn write_u8(&mut self, address: u16, value: u8) {
-        self.buf.write_u8(address, value)
-    }
-
-    pub fn write_u16(&mut self, address: u16, value: u16) {
-        self.write_u8(address, (value & 0x00ff) as u8);
-        self.write_u8(address + 1, ((value & 0xff00) >> 8) as u8);
-    }
-
-    pub fn read_u16wrapper(&mut self, address: u16) -> U16Wrapper {
-        self.buf.read_u16wrapper(address)
-    }
-
-    pub fn load_cart(&mut self,rom_bank: Buffer<0x4000>, address: usize){
-
-        assert!(self.buf.buf.len() >= 0x4000);
-        self.buf.buf[..0x4000].copy_from_slice(&rom_bank.buf)
-    }
-}
+use core::fmt;
+use std::error::Error;
 
 pub struct Buffer<const N: usize> {
     pub buf: [u8; N],
@@ -70,14 +26,15 @@ impl<const N: usize> ReadBuffer for Buffer<N> {
     }
 }
 impl<const N: usize> WriteBuffer for Buffer<N> {
-    fn write_u8(&mut self, address: u16, value: u8) {
+    fn write_u8(&mut self, address: u16, value: u8) -> Result<(), OutOfBoundsError> {
         if let Some(x) = self.buf.get_mut(address as usize) {
             *x = value;
+            Ok(())
         } else {
-            panic!(
-                "Index {address} into Buffer out of bounds, length is {}",
-                self.buf.len()
-            )
+            Err(OutOfBoundsError {
+                address,
+                len: self.buf.len(),
+            })
         }
     }
 }
@@ -88,9 +45,27 @@ pub trait ReadBuffer {
     fn read_u8(&self, address: u16) -> u8;
 }
 pub trait WriteBuffer {
-    fn write_u8(&mut self, address: u16, value: u8);
+    fn write_u8(&mut self, address: u16, value: u8) -> Result<(), OutOfBoundsError>;
 }
 
+#[derive(Debug)]
+pub struct OutOfBoundsError {
+    pub address: u16,
+    pub len: usize,
+}
+
+impl fmt::Display for OutOfBoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Index {} into Buffer out of bounds, length is {}",
+            self.address, self.len
+        )
+    }
+}
+
+impl Error for OutOfBoundsError {}
+
 #[derive(Debug)]
 pub struct U16Wrapper<'a>(pub &'a mut u8, pub &'a mut u8);
 
@@ -107,59 +82,22 @@ impl<'a> U16Wrapper<'a> {
 
 #[cfg(test)]
 mod memory_tests {
-    use crate::emulator::memory::{ReadBuffer, U16Wrapper, WriteBuffer};
-
-    use super::Memory;
-
-    #[test]
-    fn test_get_u8() {
-        let mut test_memory = Memory::new();
-
-        test_memory.buf.buf[0x0000] = 69;
-
-        assert_eq!(test_memory.read_u8(0x0000), 69);
-    }
-
-    #[test]
-    fn test_get_u16() {
-        let mut test_memory = Memory::new();
-        test_memory.buf.buf[0] = 0xFA;
-        test_memory.buf.buf[1] = 0xAF;
-
-        assert_eq!(test_memory.read_u16(0), 0xAFFA)
-    }
+    use super::{Buffer, ReadBuffer, WriteBuffer};
 
     #[test]
     fn test_write_u8() {
-        let mut test_memory = Memory::new();
-        test_memory.write_u8(0, 69);
-        assert_eq!(test_memory.buf.buf[0], 69);
-    }
+        let mut buffer = Buffer::<0x10>::default();
+        buffer.write_u8(0xF, 69).unwrap();
 
-    #[test]
-    fn test_write_u16() {
-        let mut test_memory = Memory::new();
-        test_memory.write_u16(0, 0xAFFA);
-        assert_eq!(test_memory.buf.buf[0], 0xFA);
-        assert_eq!(test_memory.buf.buf[1], 0xAF);
+        assert_eq!(buffer.read_u8(0xF), 69);
     }
 
     #[test]
-    fn test_read_u16wrapper() {
-        let mut test_memory = Memory::new();
-        test_memory.write_u8(10, 0xF0);
-        test_memory.write_u8(11, 0x0F);
-
-        let U16Wrapper(val1, val2) = test_memory.read_u16wrapper(10);
-
-        // sanity check values returned from function
-        assert_eq!(*val1, 0xF0);
-        assert_eq!(*val2, 0x0F);
-
-        // test interior mutability
-        *val1 = 69;
-        *val2 = 99;
-        assert_eq!(test_memory.read_u8(10), 69);
-        assert_eq!(test_memory.read_u8(11), 99);
+    fn test_write_u8_out_of_bounds() {
+        let mut buffer = Buffer::<0x10>::default();
+        let err = buffer.write_u8(0x10, 69).unwrap_err();
+
+        assert_eq!(err.address, 0x10);
+        assert_eq!(err.len, 0x10);
     }
 }
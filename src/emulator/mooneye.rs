@@ -0,0 +1,73 @@
+//! Runs Mooneye Test Suite ROMs headlessly. Like the screenshot tests these
+//! need `TEST_ROM_DIR`, here pointing at a directory holding a built
+//! `mooneye-test-suite/`.
+use std::path::Path;
+
+use super::{screenshots::test_rom_dir, EmulatorBuilder, CYCLES_PER_FRAME};
+
+/// Mooneye tests stop on this as a debugger breakpoint once they're done
+const LD_B_B: u8 = 0x40;
+/// B, C, D, E, H and L hold the start of the Fibonacci sequence on a pass
+const PASS_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+/// Plenty for any of the tests to finish
+const TIMEOUT_FRAMES: u64 = 600;
+
+const TIMER_TESTS: [&str; 13] = [
+    "div_write",
+    "rapid_toggle",
+    "tim00",
+    "tim00_div_trigger",
+    "tim01",
+    "tim01_div_trigger",
+    "tim10",
+    "tim10_div_trigger",
+    "tim11",
+    "tim11_div_trigger",
+    "tima_reload",
+    "tima_write_reloading",
+    "tma_write_reloading",
+];
+
+/// Runs `rom` until it hits the breakpoint and checks for the pass signature
+fn check_rom(rom: &Path) -> Result<(), String> {
+    let mut emulator = EmulatorBuilder::new()
+        .cart(rom.to_str().unwrap().to_string())
+        .map_err(|e| e.to_string())?
+        .build();
+
+    while emulator.cycles() < TIMEOUT_FRAMES * CYCLES_PER_FRAME {
+        let registers = emulator.cpu.registers();
+        if emulator.bus.read_u8(registers.pc) == LD_B_B {
+            let result = [
+                registers.b,
+                registers.c,
+                registers.d,
+                registers.e,
+                registers.h,
+                registers.l,
+            ];
+            return if result == PASS_SIGNATURE {
+                Ok(())
+            } else {
+                Err(format!("{}: failed with {:02x?}", rom.display(), result))
+            };
+        }
+        emulator.step().map_err(|e| e.to_string())?;
+    }
+    Err(format!("{}: timed out", rom.display()))
+}
+
+#[test]
+#[ignore = "needs mooneye-test-suite under TEST_ROM_DIR"]
+fn test_mooneye_timer() {
+    let dir = test_rom_dir("mooneye-test-suite");
+
+    let failures: Vec<String> = TIMER_TESTS
+        .iter()
+        .filter_map(|name| {
+            check_rom(&dir.join("acceptance/timer").join(format!("{}.gb", name))).err()
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,942 @@
+mod fifo;
+pub mod sprite;
+
+use self::{
+    fifo::Fifo,
+    sprite::{Sprite, BG_PRIORITY, PALETTE, X_FLIP, Y_FLIP},
+};
+use super::{interrupts::Interrupt, memory::Buffer};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+pub const LCDC_ADDRESS: u16 = 0xFF40;
+pub const STAT_ADDRESS: u16 = 0xFF41;
+pub const SCY_ADDRESS: u16 = 0xFF42;
+pub const SCX_ADDRESS: u16 = 0xFF43;
+pub const LY_ADDRESS: u16 = 0xFF44;
+pub const LYC_ADDRESS: u16 = 0xFF45;
+pub const BGP_ADDRESS: u16 = 0xFF47;
+pub const OBP0_ADDRESS: u16 = 0xFF48;
+pub const OBP1_ADDRESS: u16 = 0xFF49;
+pub const WY_ADDRESS: u16 = 0xFF4A;
+pub const WX_ADDRESS: u16 = 0xFF4B;
+
+const VRAM_START: u16 = 0x8000;
+const OAM_START: u16 = 0xFE00;
+/// Tile maps as offsets into VRAM
+const TILE_MAP_LOW: usize = 0x1800;
+const TILE_MAP_HIGH: usize = 0x1C00;
+/// Base of the signed tile data addressing mode, as an offset into VRAM
+const SIGNED_TILE_BASE: i32 = 0x1000;
+
+const DOTS_PER_LINE: u16 = 456;
+const LINES_PER_FRAME: u8 = 154;
+const OAM_SCAN_DOTS: u16 = 80;
+/// Shortest drawing period, before fine scroll, window and sprite penalties
+const DRAWING_DOTS: u16 = 172;
+/// Fetching the window's first tile restarts the background fetcher
+const WINDOW_PENALTY: u16 = 6;
+/// Every sprite fetch stalls drawing at least this long
+const SPRITE_PENALTY: u16 = 6;
+
+const LCD_ENABLE: u8 = 0x80;
+const WINDOW_TILE_MAP: u8 = 0x40;
+const WINDOW_ENABLE: u8 = 0x20;
+const TILE_DATA: u8 = 0x10;
+const BG_TILE_MAP: u8 = 0x08;
+const OBJ_SIZE: u8 = 0x04;
+const OBJ_ENABLE: u8 = 0x02;
+const BG_ENABLE: u8 = 0x01;
+
+/// STAT bits 3-6 are the interrupt selects, the rest is read-only
+const STAT_WRITABLE: u8 = 0x78;
+const STAT_LYC_SELECT: u8 = 0x40;
+const STAT_OAM_SELECT: u8 = 0x20;
+const STAT_VBLANK_SELECT: u8 = 0x10;
+const STAT_HBLANK_SELECT: u8 = 0x08;
+const STAT_COINCIDENCE: u8 = 0x04;
+
+/// The window is drawn from this X position, WX holds it plus 7
+const WINDOW_X_OFFSET: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+/// How the PPU turns VRAM into pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Renderer {
+    /// Draws each line in one go as its drawing period ends. Fast, but
+    /// registers written mid-line only show from the next line.
+    #[default]
+    Scanline,
+    /// Runs the background and object fetchers and pixel FIFOs dot by dot,
+    /// so mid-line register writes land on the pixels they should.
+    Fifo,
+}
+
+/// Picture processing unit: owns VRAM, OAM and the LCD registers, and draws
+/// each scanline into the framebuffer as it finishes.
+pub struct Ppu {
+    vram: Buffer<0x2000>,
+    oam: Buffer<0xA0>,
+    lcdc: u8,
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    renderer: Renderer,
+    /// Fetcher and FIFO state for `Renderer::Fifo`
+    fifo: Fifo,
+    mode: Mode,
+    /// Dots into the current line
+    dot: u16,
+    /// Length of this line's drawing period
+    drawing_dots: u16,
+    /// Objects found by this line's OAM scan, in priority order
+    line_sprites: Vec<Sprite>,
+    /// The STAT interrupt fires on the rising edge of all its enabled
+    /// sources ORed together, so one source can block another
+    stat_line: bool,
+    /// Interrupts raised since the bus last collected them
+    requested: u8,
+    /// Set once LY has matched WY this frame, the window can't show before
+    window_y_triggered: bool,
+    /// Window row to draw next, only advances on lines the window was drawn
+    window_line: u8,
+    /// DMG shades, 0 (lightest) to 3 (darkest), row by row
+    framebuffer: Box<[u8; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+}
+
+impl Default for Ppu {
+    /// Register state as the boot ROM leaves it
+    fn default() -> Self {
+        Self {
+            vram: Buffer::default(),
+            oam: Buffer::default(),
+            lcdc: 0x91,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+            renderer: Renderer::default(),
+            fifo: Fifo::default(),
+            mode: Mode::OamScan,
+            dot: 0,
+            drawing_dots: DRAWING_DOTS,
+            line_sprites: vec![],
+            stat_line: false,
+            requested: 0,
+            window_y_triggered: false,
+            window_line: 0,
+            framebuffer: Box::new([0; SCREEN_WIDTH * SCREEN_HEIGHT]),
+        }
+    }
+}
+
+impl Ppu {
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        self.framebuffer.as_slice()
+    }
+
+    /// Interrupts raised since the last call, as IF bits
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.requested)
+    }
+
+    /// The CPU can't reach VRAM while it's being drawn from
+    pub fn vram_accessible(&self) -> bool {
+        self.mode != Mode::Drawing
+    }
+
+    /// Nor OAM while it's being scanned or drawn from
+    pub fn oam_accessible(&self) -> bool {
+        !matches!(self.mode, Mode::OamScan | Mode::Drawing)
+    }
+
+    pub fn read_vram(&self, address: u16) -> u8 {
+        self.vram.buf[(address - VRAM_START) as usize]
+    }
+
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        self.vram.buf[(address - VRAM_START) as usize] = value;
+    }
+
+    pub fn read_oam(&self, address: u16) -> u8 {
+        self.oam.buf[(address - OAM_START) as usize]
+    }
+
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        self.oam.buf[(address - OAM_START) as usize] = value;
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            LCDC_ADDRESS => self.lcdc,
+            STAT_ADDRESS => {
+                let mut value = 0x80 | self.stat | self.mode as u8;
+                if self.ly == self.lyc {
+                    value |= STAT_COINCIDENCE;
+                }
+                value
+            }
+            SCY_ADDRESS => self.scy,
+            SCX_ADDRESS => self.scx,
+            LY_ADDRESS => self.ly,
+            LYC_ADDRESS => self.lyc,
+            BGP_ADDRESS => self.bgp,
+            OBP0_ADDRESS => self.obp0,
+            OBP1_ADDRESS => self.obp1,
+            WY_ADDRESS => self.wy,
+            WX_ADDRESS => self.wx,
+            _ => panic!("Address {:#06x} is not a PPU register", address),
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            LCDC_ADDRESS => self.write_lcdc(value),
+            STAT_ADDRESS => {
+                self.stat = value & STAT_WRITABLE;
+                self.update_stat_line();
+            }
+            SCY_ADDRESS => self.scy = value,
+            SCX_ADDRESS => self.scx = value,
+            // LY is read-only
+            LY_ADDRESS => {}
+            LYC_ADDRESS => {
+                self.lyc = value;
+                self.update_stat_line();
+            }
+            BGP_ADDRESS => self.bgp = value,
+            OBP0_ADDRESS => self.obp0 = value,
+            OBP1_ADDRESS => self.obp1 = value,
+            WY_ADDRESS => self.wy = value,
+            WX_ADDRESS => self.wx = value,
+            _ => panic!("Address {:#06x} is not a PPU register", address),
+        }
+    }
+
+    fn write_lcdc(&mut self, value: u8) {
+        let was_enabled = self.lcdc & LCD_ENABLE != 0;
+        self.lcdc = value;
+        match (was_enabled, value & LCD_ENABLE != 0) {
+            // switching off parks the PPU at the top of the screen in HBlank
+            (true, false) => {
+                self.ly = 0;
+                self.dot = 0;
+                self.mode = Mode::HBlank;
+                self.stat_line = false;
+            }
+            (false, true) => self.start_frame(),
+            _ => {}
+        }
+    }
+
+    /// Advances by `cycles` dots, one dot per T-cycle
+    pub fn tick(&mut self, cycles: u8) {
+        if self.lcdc & LCD_ENABLE == 0 {
+            return;
+        }
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        self.dot += 1;
+        match self.mode {
+            Mode::OamScan if self.dot == OAM_SCAN_DOTS => {
+                self.line_sprites = sprite::scan(&self.oam.buf, self.ly, self.sprite_height());
+                match self.renderer {
+                    Renderer::Scanline => self.drawing_dots = self.drawing_length(),
+                    Renderer::Fifo => self.fifo = Fifo::new(self.scx),
+                }
+                self.set_mode(Mode::Drawing);
+            }
+            Mode::Drawing => {
+                let done = match self.renderer {
+                    Renderer::Scanline => {
+                        let done = self.dot == OAM_SCAN_DOTS + self.drawing_dots;
+                        if done {
+                            self.render_line();
+                        }
+                        done
+                    }
+                    Renderer::Fifo => self.fifo_step(),
+                };
+                if done {
+                    self.set_mode(Mode::HBlank);
+                }
+            }
+            _ if self.dot == DOTS_PER_LINE => self.next_line(),
+            _ => {}
+        }
+    }
+
+    fn next_line(&mut self) {
+        self.dot = 0;
+        self.ly += 1;
+        if self.ly == LINES_PER_FRAME {
+            self.start_frame();
+        } else if self.ly as usize == SCREEN_HEIGHT {
+            self.requested |= Interrupt::VBlank.mask();
+            self.set_mode(Mode::VBlank);
+        } else if (self.ly as usize) < SCREEN_HEIGHT {
+            self.start_line();
+        } else {
+            // LY changed, which can still match LYC
+            self.update_stat_line();
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.update_stat_line();
+    }
+
+    /// Requests the STAT interrupt if its line has just gone high
+    fn update_stat_line(&mut self) {
+        let line = (self.stat & STAT_LYC_SELECT != 0 && self.ly == self.lyc)
+            || match self.mode {
+                Mode::HBlank => self.stat & STAT_HBLANK_SELECT != 0,
+                Mode::VBlank => self.stat & STAT_VBLANK_SELECT != 0,
+                Mode::OamScan => self.stat & STAT_OAM_SELECT != 0,
+                Mode::Drawing => false,
+            };
+        if line && !self.stat_line {
+            self.requested |= Interrupt::LcdStat.mask();
+        }
+        self.stat_line = line;
+    }
+
+    fn window_on_line(&self) -> bool {
+        self.lcdc & BG_ENABLE != 0
+            && self.lcdc & WINDOW_ENABLE != 0
+            && self.window_y_triggered
+            && self.wx < SCREEN_WIDTH as u8 + WINDOW_X_OFFSET
+    }
+
+    /// Dots spent drawing this line: fine scroll pixels are fetched and
+    /// thrown away, the window restarts the fetcher and every sprite stalls
+    /// it, for longer when it sits early in a background tile
+    fn drawing_length(&self) -> u16 {
+        let fine_scroll = (self.scx % 8) as u16;
+        let mut dots = DRAWING_DOTS + fine_scroll;
+        if self.window_on_line() {
+            dots += WINDOW_PENALTY;
+        }
+        if self.lcdc & OBJ_ENABLE == 0 {
+            return dots;
+        }
+
+        let mut fetched_tiles = vec![];
+        for sprite in &self.line_sprites {
+            // sprites past the right edge are never fetched
+            if sprite.x as usize >= SCREEN_WIDTH + 8 {
+                continue;
+            }
+            dots += self.sprite_penalty(sprite, &mut fetched_tiles);
+        }
+        dots
+    }
+
+    /// Dots fetching `sprite` stalls drawing for. Only the first sprite in
+    /// each background tile waits for that tile's fetch to finish,
+    /// `fetched_tiles` keeps track of them.
+    fn sprite_penalty(&self, sprite: &Sprite, fetched_tiles: &mut Vec<u16>) -> u16 {
+        let x = sprite.x as u16 + (self.scx % 8) as u16;
+        if fetched_tiles.contains(&(x / 8)) {
+            return SPRITE_PENALTY;
+        }
+        fetched_tiles.push(x / 8);
+        SPRITE_PENALTY
+            + if sprite.x == 0 {
+                5
+            } else {
+                5u16.saturating_sub(x % 8)
+            }
+    }
+
+    fn start_frame(&mut self) {
+        self.ly = 0;
+        self.dot = 0;
+        self.window_y_triggered = false;
+        self.window_line = 0;
+        self.start_line();
+    }
+
+    fn start_line(&mut self) {
+        if self.ly == self.wy {
+            self.window_y_triggered = true;
+        }
+        self.set_mode(Mode::OamScan);
+    }
+
+    fn render_line(&mut self) {
+        let y = self.ly;
+        let window = self.window_on_line();
+        let mut window_drawn = false;
+
+        let mut bg = [0; SCREEN_WIDTH];
+        for (x, color) in (0..).zip(bg.iter_mut()) {
+            if self.lcdc & BG_ENABLE == 0 {
+                break;
+            }
+            *color = if window && x + WINDOW_X_OFFSET >= self.wx {
+                window_drawn = true;
+                self.tile_pixel(
+                    self.lcdc & WINDOW_TILE_MAP != 0,
+                    x + WINDOW_X_OFFSET - self.wx,
+                    self.window_line,
+                )
+            } else {
+                self.tile_pixel(
+                    self.lcdc & BG_TILE_MAP != 0,
+                    self.scx.wrapping_add(x),
+                    self.scy.wrapping_add(y),
+                )
+            };
+        }
+
+        let sprites = if self.lcdc & OBJ_ENABLE != 0 {
+            std::mem::take(&mut self.line_sprites)
+        } else {
+            vec![]
+        };
+
+        for (x, &color) in (0..).zip(bg.iter()) {
+            let shade = self
+                .sprite_pixel(&sprites, x, color)
+                .unwrap_or_else(|| palette(self.bgp, color));
+            self.framebuffer[y as usize * SCREEN_WIDTH + x as usize] = shade;
+        }
+
+        if window_drawn {
+            self.window_line += 1;
+        }
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.lcdc & OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Shade of the sprite pixel drawn over background color `bg` at `x`, if
+    /// any. `sprites` is in priority order, the first opaque pixel wins even
+    /// when it then hides behind the background.
+    fn sprite_pixel(&self, sprites: &[Sprite], x: u8, bg: u8) -> Option<u8> {
+        let (sprite, color) = sprites.iter().find_map(|sprite| {
+            let color = self.sprite_color(sprite, sprite.column(x)?);
+            (color != 0).then_some((sprite, color))
+        })?;
+
+        if sprite.flags & BG_PRIORITY != 0 && bg != 0 {
+            return None;
+        }
+        let palette_register = if sprite.flags & PALETTE != 0 {
+            self.obp1
+        } else {
+            self.obp0
+        };
+        Some(palette(palette_register, color))
+    }
+
+    /// Color index (0-3) of `sprite` at `column` on the current line
+    fn sprite_color(&self, sprite: &Sprite, column: u8) -> u8 {
+        let Some(address) = self.sprite_row_address(sprite) else {
+            return 0;
+        };
+        let (low, high) = (self.vram.buf[address], self.vram.buf[address + 1]);
+        sprite_row_color(sprite, low, high, column)
+    }
+
+    /// VRAM address of the row of `sprite` on the current line, `None` if
+    /// it doesn't cover the line at the current sprite height
+    fn sprite_row_address(&self, sprite: &Sprite) -> Option<usize> {
+        let height = self.sprite_height();
+        let mut row = sprite.row(self.ly, height)?;
+        if sprite.flags & Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+        // 8x16 sprites ignore the low bit, the bottom half is the next tile
+        let tile = if height == 16 {
+            sprite.tile & 0xFE
+        } else {
+            sprite.tile
+        };
+
+        Some(tile as usize * 16 + row as usize * 2)
+    }
+
+    /// Color index (0-3) at (`x`, `y`) of the 256x256 tile map
+    fn tile_pixel(&self, high_map: bool, x: u8, y: u8) -> u8 {
+        let tile = self.tile_number(high_map, x / 8, y / 8);
+        let row = self.tile_row_address(tile, y % 8);
+        let (low, high) = (self.vram.buf[row], self.vram.buf[row + 1]);
+
+        let bit = 7 - x % 8;
+        (high >> bit & 1) << 1 | (low >> bit & 1)
+    }
+
+    /// Tile at (`column`, `row`) of one of the 32x32 tile maps
+    fn tile_number(&self, high_map: bool, column: u8, row: u8) -> u8 {
+        let map = if high_map {
+            TILE_MAP_HIGH
+        } else {
+            TILE_MAP_LOW
+        };
+        self.vram.buf[map + (row as usize % 32) * 32 + column as usize % 32]
+    }
+
+    /// VRAM offset of the low byte of `row` in `tile`, in the addressing
+    /// mode LCDC selects
+    fn tile_row_address(&self, tile: u8, row: u8) -> usize {
+        let tile_start = if self.lcdc & TILE_DATA != 0 {
+            tile as usize * 16
+        } else {
+            (SIGNED_TILE_BASE + tile as i8 as i32 * 16) as usize
+        };
+        tile_start + row as usize * 2
+    }
+}
+
+/// Maps a color index through a BGP/OBP style palette to a shade
+fn palette(palette: u8, color: u8) -> u8 {
+    palette >> (color * 2) & 0x03
+}
+
+/// Color index (0-3) of `sprite` at `column` in a row of its tile data
+fn sprite_row_color(sprite: &Sprite, low: u8, high: u8, column: u8) -> u8 {
+    let bit = if sprite.flags & X_FLIP != 0 {
+        column
+    } else {
+        7 - column
+    };
+    (high >> bit & 1) << 1 | (low >> bit & 1)
+}
+
+#[cfg(test)]
+mod ppu_tests {
+    use super::{
+        Mode, Ppu, BGP_ADDRESS, DOTS_PER_LINE, LCDC_ADDRESS, LINES_PER_FRAME, LYC_ADDRESS,
+        LY_ADDRESS, OBP0_ADDRESS, OBP1_ADDRESS, SCREEN_WIDTH, SCX_ADDRESS, STAT_ADDRESS,
+        WX_ADDRESS, WY_ADDRESS,
+    };
+
+    /// LCD and background on, unsigned tile data, identity palette
+    fn setup(lcdc: u8) -> Ppu {
+        let mut ppu = Ppu::default();
+        ppu.write_register(LCDC_ADDRESS, 0x00);
+        ppu.write_register(BGP_ADDRESS, 0xE4);
+        ppu.write_register(OBP0_ADDRESS, 0xE4);
+        ppu.write_register(OBP1_ADDRESS, 0xE4);
+        ppu.write_register(LCDC_ADDRESS, lcdc);
+        ppu
+    }
+
+    /// Fills the tile starting at `address` with one color
+    fn solid_tile(ppu: &mut Ppu, address: u16, color: u8) {
+        for row in 0..8 {
+            let low = if color & 1 != 0 { 0xFF } else { 0x00 };
+            let high = if color & 2 != 0 { 0xFF } else { 0x00 };
+            ppu.write_vram(address + row * 2, low);
+            ppu.write_vram(address + row * 2 + 1, high);
+        }
+    }
+
+    fn run_lines(ppu: &mut Ppu, lines: u16) {
+        for _ in 0..lines as u32 * DOTS_PER_LINE as u32 / 4 {
+            ppu.tick(4);
+        }
+    }
+
+    /// Places OAM entry `index` with the sprite's top left at screen (`x`, `y`)
+    fn sprite(ppu: &mut Ppu, index: u16, x: u8, y: u8, tile: u8, flags: u8) {
+        let address = 0xFE00 + index * 4;
+        ppu.write_oam(address, y + 16);
+        ppu.write_oam(address + 1, x + 8);
+        ppu.write_oam(address + 2, tile);
+        ppu.write_oam(address + 3, flags);
+    }
+
+    fn run_dots(ppu: &mut Ppu, dots: u16) {
+        for _ in 0..dots {
+            ppu.tick(1);
+        }
+    }
+
+    /// Dots from the start of the line until HBlank, then runs on to the
+    /// start of the next line
+    fn drawing_end(ppu: &mut Ppu) -> u16 {
+        let mut dots = 0;
+        while ppu.mode != Mode::HBlank {
+            ppu.tick(1);
+            dots += 1;
+        }
+        run_dots(ppu, DOTS_PER_LINE - dots);
+        dots
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> u8 {
+        ppu.framebuffer()[y * SCREEN_WIDTH + x]
+    }
+
+    #[test]
+    fn test_unsigned_tile_data() {
+        let mut ppu = setup(0x91);
+        solid_tile(&mut ppu, 0x8010, 3);
+        ppu.write_vram(0x9800, 1);
+
+        run_lines(&mut ppu, LINES_PER_FRAME as u16);
+
+        assert_eq!(pixel(&ppu, 0, 0), 3);
+        assert_eq!(pixel(&ppu, 7, 7), 3);
+        assert_eq!(pixel(&ppu, 8, 0), 0);
+        assert_eq!(pixel(&ppu, 0, 8), 0);
+    }
+
+    #[test]
+    fn test_signed_tile_data() {
+        let mut ppu = setup(0x81);
+        // tile -1 sits just below 0x9000, tile 0 at 0x9000 rather than 0x8000
+        solid_tile(&mut ppu, 0x8FF0, 2);
+        solid_tile(&mut ppu, 0x8000, 3);
+        ppu.write_vram(0x9800, 0xFF);
+
+        run_lines(&mut ppu, LINES_PER_FRAME as u16);
+
+        assert_eq!(pixel(&ppu, 0, 0), 2);
+        assert_eq!(pixel(&ppu, 8, 0), 0);
+    }
+
+    #[test]
+    fn test_scroll_wraps() {
+        let mut ppu = setup(0x91);
+        solid_tile(&mut ppu, 0x8010, 3);
+        ppu.write_vram(0x9800, 1);
+        ppu.write_register(SCX_ADDRESS, 252);
+
+        run_lines(&mut ppu, 1);
+
+        // the last 4 columns of the map, then its first tile
+        assert_eq!(pixel(&ppu, 3, 0), 0);
+        assert_eq!(pixel(&ppu, 4, 0), 3);
+        assert_eq!(pixel(&ppu, 11, 0), 3);
+        assert_eq!(pixel(&ppu, 12, 0), 0);
+    }
+
+    #[test]
+    fn test_palette() {
+        let mut ppu = setup(0x91);
+        solid_tile(&mut ppu, 0x8010, 3);
+        ppu.write_vram(0x9800, 1);
+        ppu.write_register(BGP_ADDRESS, 0x1B);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+        assert_eq!(pixel(&ppu, 8, 0), 3);
+    }
+
+    #[test]
+    fn test_window_line_counter() {
+        // window on the high map, background on the low one
+        let mut ppu = setup(0xF1);
+        solid_tile(&mut ppu, 0x8010, 1);
+        solid_tile(&mut ppu, 0x8020, 2);
+        ppu.write_vram(0x9C00, 1);
+        ppu.write_vram(0x9C20, 2);
+        ppu.write_register(WY_ADDRESS, 0);
+        ppu.write_register(WX_ADDRESS, 7 + 8);
+
+        run_lines(&mut ppu, 4);
+        ppu.write_register(LCDC_ADDRESS, 0xD1);
+        run_lines(&mut ppu, 6);
+        ppu.write_register(LCDC_ADDRESS, 0xF1);
+        run_lines(&mut ppu, 6);
+
+        // left of WX is background
+        assert_eq!(pixel(&ppu, 7, 0), 0);
+        assert_eq!(pixel(&ppu, 8, 0), 1);
+        assert_eq!(pixel(&ppu, 8, 5), 0);
+        // lines 10-13 carry on from window line 4, still the first tile row
+        assert_eq!(pixel(&ppu, 8, 13), 1);
+        assert_eq!(pixel(&ppu, 8, 14), 2);
+    }
+
+    #[test]
+    fn test_bg_disabled_is_blank() {
+        let mut ppu = setup(0x90);
+        solid_tile(&mut ppu, 0x8000, 3);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_lcd_off_resets_ly() {
+        let mut ppu = setup(0x91);
+        run_lines(&mut ppu, 3);
+        assert_eq!(ppu.ly, 3);
+
+        ppu.write_register(LCDC_ADDRESS, 0x11);
+        run_lines(&mut ppu, 3);
+
+        assert_eq!(ppu.ly, 0);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x03, 0);
+    }
+
+    #[test]
+    fn test_sprite_over_background() {
+        let mut ppu = setup(0x93);
+        solid_tile(&mut ppu, 0x8010, 2);
+        sprite(&mut ppu, 0, 4, 0, 1, 0x00);
+
+        run_lines(&mut ppu, 8);
+
+        assert_eq!(pixel(&ppu, 3, 0), 0);
+        assert_eq!(pixel(&ppu, 4, 0), 2);
+        assert_eq!(pixel(&ppu, 11, 7), 2);
+        assert_eq!(pixel(&ppu, 12, 7), 0);
+    }
+
+    #[test]
+    fn test_sprite_palettes() {
+        let mut ppu = setup(0x93);
+        solid_tile(&mut ppu, 0x8010, 1);
+        sprite(&mut ppu, 0, 0, 0, 1, 0x00);
+        sprite(&mut ppu, 1, 8, 0, 1, 0x10);
+        ppu.write_register(OBP0_ADDRESS, 0x08);
+        ppu.write_register(OBP1_ADDRESS, 0x0C);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), 2);
+        assert_eq!(pixel(&ppu, 8, 0), 3);
+    }
+
+    #[test]
+    fn test_sprite_flips() {
+        let mut ppu = setup(0x93);
+        // only the top left pixel set
+        ppu.write_vram(0x8010, 0x80);
+        ppu.write_vram(0x8011, 0x80);
+        sprite(&mut ppu, 0, 0, 0, 1, 0x20);
+        sprite(&mut ppu, 1, 16, 0, 1, 0x40);
+
+        run_lines(&mut ppu, 8);
+
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+        assert_eq!(pixel(&ppu, 7, 0), 3);
+        assert_eq!(pixel(&ppu, 16, 0), 0);
+        assert_eq!(pixel(&ppu, 16, 7), 3);
+    }
+
+    #[test]
+    fn test_tall_sprites() {
+        let mut ppu = setup(0x97);
+        solid_tile(&mut ppu, 0x8020, 1);
+        solid_tile(&mut ppu, 0x8030, 2);
+        // the low bit of the tile index is ignored
+        sprite(&mut ppu, 0, 0, 0, 3, 0x00);
+        sprite(&mut ppu, 1, 8, 0, 2, 0x40);
+
+        run_lines(&mut ppu, 16);
+
+        assert_eq!(pixel(&ppu, 0, 0), 1);
+        assert_eq!(pixel(&ppu, 0, 15), 2);
+        assert_eq!(pixel(&ppu, 8, 0), 2);
+        assert_eq!(pixel(&ppu, 8, 15), 1);
+    }
+
+    #[test]
+    fn test_sprite_priority() {
+        let mut ppu = setup(0x93);
+        solid_tile(&mut ppu, 0x8010, 1);
+        solid_tile(&mut ppu, 0x8020, 2);
+        solid_tile(&mut ppu, 0x8030, 3);
+        // lower X wins even from later in OAM
+        sprite(&mut ppu, 0, 4, 0, 1, 0x00);
+        sprite(&mut ppu, 1, 0, 0, 2, 0x00);
+        // same X, the earlier OAM entry wins
+        sprite(&mut ppu, 2, 40, 0, 3, 0x00);
+        sprite(&mut ppu, 3, 40, 0, 1, 0x00);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 4, 0), 2);
+        assert_eq!(pixel(&ppu, 8, 0), 1);
+        assert_eq!(pixel(&ppu, 40, 0), 3);
+    }
+
+    #[test]
+    fn test_bg_priority_hides_lower_sprites() {
+        let mut ppu = setup(0x93);
+        solid_tile(&mut ppu, 0x8010, 1);
+        solid_tile(&mut ppu, 0x8020, 2);
+        ppu.write_vram(0x9801, 1);
+        // behind BG, drawn only over color 0
+        sprite(&mut ppu, 0, 4, 0, 2, 0x80);
+        // would be visible at 8-11 were it not under the priority sprite
+        sprite(&mut ppu, 1, 8, 0, 2, 0x00);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 4, 0), 2);
+        assert_eq!(pixel(&ppu, 8, 0), 1);
+        assert_eq!(pixel(&ppu, 12, 0), 2);
+    }
+
+    #[test]
+    fn test_ten_sprites_per_line() {
+        let mut ppu = setup(0x93);
+        solid_tile(&mut ppu, 0x8010, 3);
+        for i in 0..11 {
+            sprite(&mut ppu, i, i as u8 * 8, 0, 1, 0x00);
+        }
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 72, 0), 3);
+        assert_eq!(pixel(&ppu, 80, 0), 0);
+    }
+
+    #[test]
+    fn test_objects_disabled() {
+        let mut ppu = setup(0x91);
+        solid_tile(&mut ppu, 0x8010, 3);
+        sprite(&mut ppu, 0, 0, 0, 1, 0x00);
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(pixel(&ppu, 0, 0), 0);
+    }
+
+    #[test]
+    fn test_mode_sequence() {
+        let mut ppu = setup(0x91);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x03, 2);
+
+        run_dots(&mut ppu, 80);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x03, 3);
+        assert!(!ppu.vram_accessible());
+        assert!(!ppu.oam_accessible());
+
+        run_dots(&mut ppu, 172);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x03, 0);
+        assert!(ppu.oam_accessible());
+
+        run_dots(&mut ppu, 204);
+        assert_eq!(ppu.read_register(LY_ADDRESS), 1);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x03, 2);
+    }
+
+    #[test]
+    fn test_vblank_interrupt() {
+        let mut ppu = setup(0x91);
+        run_lines(&mut ppu, 143);
+        ppu.take_interrupts();
+
+        run_lines(&mut ppu, 1);
+
+        assert_eq!(ppu.read_register(LY_ADDRESS), 144);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x03, 1);
+        assert_eq!(ppu.take_interrupts(), 0x01);
+
+        run_lines(&mut ppu, 10);
+        assert_eq!(ppu.read_register(LY_ADDRESS), 0);
+        assert_eq!(ppu.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_drawing_length() {
+        let mut ppu = setup(0x93);
+        assert_eq!(drawing_end(&mut ppu), 252);
+
+        // fine scroll is fetched and thrown away
+        ppu.write_register(SCX_ADDRESS, 3);
+        assert_eq!(drawing_end(&mut ppu), 255);
+
+        // window restart
+        ppu.write_register(SCX_ADDRESS, 0);
+        ppu.write_register(LCDC_ADDRESS, 0xB3);
+        ppu.write_register(WX_ADDRESS, 7);
+        assert_eq!(drawing_end(&mut ppu), 258);
+
+        // a sprite on a tile boundary waits out the whole fetch, a second in
+        // the same tile only pays for its own
+        ppu.write_register(LCDC_ADDRESS, 0x93);
+        let ly = ppu.read_register(LY_ADDRESS);
+        ppu.write_oam(0xFE00, ly + 16);
+        ppu.write_oam(0xFE01, 16);
+        ppu.write_oam(0xFE04, ly + 16);
+        ppu.write_oam(0xFE05, 20);
+        assert_eq!(drawing_end(&mut ppu), 252 + 11 + 6);
+    }
+
+    #[test]
+    fn test_stat_interrupt_blocking() {
+        let mut ppu = setup(0x91);
+        // HBlank and OAM scan selected, the line stays high from one into
+        // the next so only the HBlank edge counts
+        ppu.write_register(STAT_ADDRESS, 0x28);
+        ppu.take_interrupts();
+
+        let mut count = 0;
+        for _ in 0..4 * 456 {
+            ppu.tick(1);
+            if ppu.take_interrupts() & 0x02 != 0 {
+                count += 1;
+            }
+        }
+
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_lyc_interrupt() {
+        let mut ppu = setup(0x91);
+        ppu.write_register(LYC_ADDRESS, 5);
+        ppu.write_register(STAT_ADDRESS, 0x40);
+        ppu.take_interrupts();
+
+        run_lines(&mut ppu, 4);
+        assert_eq!(ppu.take_interrupts(), 0);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x04, 0);
+
+        run_lines(&mut ppu, 1);
+        assert_eq!(ppu.take_interrupts(), 0x02);
+        assert_eq!(ppu.read_register(STAT_ADDRESS) & 0x04, 0x04);
+
+        // writing a matching LYC raises it straight away
+        run_lines(&mut ppu, 1);
+        ppu.write_register(LYC_ADDRESS, 6);
+        assert_eq!(ppu.take_interrupts(), 0x02);
+    }
+}
@@ -0,0 +1,507 @@
+//! Pixel FIFO renderer. The background fetcher fills one FIFO, sprite
+//! fetches are mixed into another, and one pixel is shifted out to the LCD
+//! per dot. Registers are read as each tile is fetched or pixel is shifted,
+//! so writes during drawing show up mid-line like they do on hardware.
+use std::collections::VecDeque;
+
+use super::{
+    palette,
+    sprite::{BG_PRIORITY, PALETTE},
+    sprite_row_color, Ppu, BG_ENABLE, BG_TILE_MAP, OBJ_ENABLE, SCREEN_WIDTH, WINDOW_TILE_MAP,
+    WINDOW_X_OFFSET,
+};
+
+/// Fetching the tile number and each byte of tile data take two dots apiece
+const FETCH_STEP_DOTS: u8 = 2;
+const TILE_WIDTH: usize = 8;
+/// Dots left in a sprite fetch when its tile step and each data read finish
+const SPRITE_TILE_DONE: u16 = 4;
+const SPRITE_LOW_DONE: u16 = 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum FetchStep {
+    #[default]
+    Tile,
+    DataLow,
+    DataHigh,
+    /// Waits here until the background FIFO runs dry
+    Push,
+}
+
+/// Sprite pixel waiting in the object FIFO, palettes are applied as it's
+/// shifted out
+#[derive(Debug, Clone, Copy, Default)]
+struct ObjPixel {
+    color: u8,
+    obp1: bool,
+    bg_priority: bool,
+}
+
+/// Sprite being fetched. The fetch itself is the last `SPRITE_FETCH_DOTS`
+/// of the stall, anything before that is waiting on the background fetcher.
+#[derive(Debug, Clone, Copy)]
+struct SpriteFetch {
+    /// Index into the line's sprites
+    index: usize,
+    /// Dots left until it's mixed in
+    dots: u16,
+    /// Row of tile data picked by the tile step, `None` if the sprite
+    /// doesn't cover the line at the sprite height read then
+    address: Option<usize>,
+    low: u8,
+}
+
+#[derive(Debug, Default)]
+pub struct Fifo {
+    /// Background and window color indices
+    bg: VecDeque<u8>,
+    obj: VecDeque<ObjPixel>,
+    step: FetchStep,
+    /// Dots spent in the current fetch step
+    step_dots: u8,
+    /// Tile column fetched next, counted from SCX or the window's left edge
+    fetch_x: u8,
+    tile: u8,
+    low: u8,
+    high: u8,
+    /// The first tile of every line is fetched twice, the first is dropped
+    first_fetch: bool,
+    /// Fine scroll pixels still to be shifted out and dropped
+    discard: u8,
+    /// Screen X of the next pixel shifted out
+    x: u8,
+    /// The fetcher has switched to the window for the rest of the line
+    window: bool,
+    /// Index into the line's sprites of the next one to fetch
+    next_sprite: usize,
+    sprite_fetch: Option<SpriteFetch>,
+    /// Background tiles sprite fetches have already waited on
+    fetched_tiles: Vec<u16>,
+}
+
+impl Fifo {
+    /// State at the start of drawing. Only the low bits of SCX are latched
+    /// here, the rest is read on every tile fetch.
+    pub fn new(scx: u8) -> Self {
+        Self {
+            first_fetch: true,
+            discard: scx % TILE_WIDTH as u8,
+            ..Default::default()
+        }
+    }
+
+    /// Lets the fetcher start over on a new tile, the dot it happens on
+    /// counts towards the tile number fetch
+    fn restart_fetcher(&mut self) {
+        self.step = FetchStep::Tile;
+        self.step_dots = 1;
+    }
+}
+
+impl Ppu {
+    /// Runs the FIFO renderer for one dot, returning whether the line's last
+    /// pixel went out
+    pub(super) fn fifo_step(&mut self) -> bool {
+        if self.fifo.sprite_fetch.is_none() {
+            self.start_sprite_fetch();
+        }
+        // a sprite fetch stalls both the fetcher and the LCD
+        if self.fifo.sprite_fetch.is_some() {
+            self.sprite_fetch_step();
+            return false;
+        }
+
+        self.fetch_step();
+        self.start_window();
+        let done = self.shift_pixel();
+        if done && self.fifo.window {
+            self.window_line += 1;
+        }
+        done
+    }
+
+    /// Starts fetching the next sprite if it begins at or before the pixel
+    /// about to be shifted out
+    fn start_sprite_fetch(&mut self) {
+        if self.lcdc & OBJ_ENABLE == 0 {
+            return;
+        }
+        let index = self.fifo.next_sprite;
+        let Some(sprite) = self.line_sprites.get(index) else {
+            return;
+        };
+        if sprite.x as usize > self.fifo.x as usize + TILE_WIDTH {
+            return;
+        }
+
+        let mut fetched_tiles = std::mem::take(&mut self.fifo.fetched_tiles);
+        let dots = self.sprite_penalty(sprite, &mut fetched_tiles);
+        self.fifo.fetched_tiles = fetched_tiles;
+        self.fifo.next_sprite += 1;
+        self.fifo.sprite_fetch = Some(SpriteFetch {
+            index,
+            dots,
+            address: None,
+            low: 0,
+        });
+    }
+
+    /// Advances a sprite fetch by one dot. Like the background fetcher it
+    /// reads the tile row, then each byte of tile data, two dots apiece, so
+    /// OBJ_SIZE and VRAM are seen as they are at each step.
+    fn sprite_fetch_step(&mut self) {
+        let Some(mut fetch) = self.fifo.sprite_fetch.take() else {
+            return;
+        };
+        fetch.dots -= 1;
+        let sprite = self.line_sprites[fetch.index];
+        match fetch.dots {
+            SPRITE_TILE_DONE => fetch.address = self.sprite_row_address(&sprite),
+            SPRITE_LOW_DONE => fetch.low = fetch.address.map_or(0, |a| self.vram.buf[a]),
+            0 => {
+                let high = fetch.address.map_or(0, |a| self.vram.buf[a + 1]);
+                self.mix_sprite(fetch.index, fetch.low, high);
+                return;
+            }
+            _ => {}
+        }
+        self.fifo.sprite_fetch = Some(fetch);
+    }
+
+    /// Mixes a fetched row of sprite tile data into the object FIFO. Pixels
+    /// already there came from a sprite with priority, only transparent
+    /// ones are replaced.
+    fn mix_sprite(&mut self, index: usize, low: u8, high: u8) {
+        let sprite = self.line_sprites[index];
+        let obj = &mut self.fifo.obj;
+        if obj.len() < TILE_WIDTH {
+            obj.resize(TILE_WIDTH, ObjPixel::default());
+        }
+
+        for i in 0..TILE_WIDTH {
+            // the part of a sprite left of the screen is never shifted out
+            let Some(column) = sprite.column(self.fifo.x + i as u8) else {
+                continue;
+            };
+            let color = sprite_row_color(&sprite, low, high, column);
+            let pixel = &mut self.fifo.obj[i];
+            if pixel.color == 0 && color != 0 {
+                *pixel = ObjPixel {
+                    color,
+                    obp1: sprite.flags & PALETTE != 0,
+                    bg_priority: sprite.flags & BG_PRIORITY != 0,
+                };
+            }
+        }
+    }
+
+    /// Advances the background fetcher by one dot
+    fn fetch_step(&mut self) {
+        let fifo = &mut self.fifo;
+        if fifo.step == FetchStep::Push {
+            if !fifo.bg.is_empty() {
+                return;
+            }
+            if fifo.first_fetch {
+                fifo.first_fetch = false;
+            } else {
+                let (low, high) = (fifo.low, fifo.high);
+                fifo.bg.extend(
+                    (0..TILE_WIDTH)
+                        .rev()
+                        .map(|bit| (high >> bit & 1) << 1 | (low >> bit & 1)),
+                );
+                fifo.fetch_x = fifo.fetch_x.wrapping_add(1);
+            }
+            fifo.restart_fetcher();
+            return;
+        }
+
+        fifo.step_dots += 1;
+        if fifo.step_dots < FETCH_STEP_DOTS {
+            return;
+        }
+        fifo.step_dots = 0;
+        match fifo.step {
+            FetchStep::Tile => {
+                self.fifo.tile = self.fetch_tile();
+                self.fifo.step = FetchStep::DataLow;
+            }
+            FetchStep::DataLow => {
+                self.fifo.low = self.vram.buf[self.fetch_row_address()];
+                self.fifo.step = FetchStep::DataHigh;
+            }
+            FetchStep::DataHigh => {
+                self.fifo.high = self.vram.buf[self.fetch_row_address() + 1];
+                self.fifo.step = FetchStep::Push;
+            }
+            FetchStep::Push => unreachable!(),
+        }
+    }
+
+    fn fetch_tile(&self) -> u8 {
+        if self.fifo.window {
+            self.tile_number(
+                self.lcdc & WINDOW_TILE_MAP != 0,
+                self.fifo.fetch_x,
+                self.window_line / 8,
+            )
+        } else {
+            self.tile_number(
+                self.lcdc & BG_TILE_MAP != 0,
+                (self.scx / 8).wrapping_add(self.fifo.fetch_x),
+                self.ly.wrapping_add(self.scy) / 8,
+            )
+        }
+    }
+
+    fn fetch_row_address(&self) -> usize {
+        let y = if self.fifo.window {
+            self.window_line
+        } else {
+            self.ly.wrapping_add(self.scy)
+        };
+        self.tile_row_address(self.fifo.tile, y % 8)
+    }
+
+    /// Switches the fetcher to the window once the pixel about to go out is
+    /// where WX puts it, throwing away the background pixels queued so far
+    fn start_window(&mut self) {
+        let fifo = &self.fifo;
+        if fifo.window || fifo.bg.is_empty() || fifo.discard > 0 || !self.window_on_line() {
+            return;
+        }
+        let x = fifo.x;
+        if x + WINDOW_X_OFFSET != self.wx && !(x == 0 && self.wx < WINDOW_X_OFFSET) {
+            return;
+        }
+
+        let fifo = &mut self.fifo;
+        fifo.window = true;
+        fifo.fetch_x = 0;
+        fifo.bg.clear();
+        fifo.restart_fetcher();
+    }
+
+    /// Shifts one pixel out to the LCD, returning whether it was the last on
+    /// the line
+    fn shift_pixel(&mut self) -> bool {
+        let fifo = &mut self.fifo;
+        let Some(color) = fifo.bg.pop_front() else {
+            return false;
+        };
+        if fifo.discard > 0 {
+            fifo.discard -= 1;
+            return false;
+        }
+        let obj = fifo.obj.pop_front().unwrap_or_default();
+        let x = fifo.x as usize;
+        fifo.x += 1;
+
+        let bg = if self.lcdc & BG_ENABLE != 0 { color } else { 0 };
+        let shade =
+            if self.lcdc & OBJ_ENABLE != 0 && obj.color != 0 && !(obj.bg_priority && bg != 0) {
+                palette(if obj.obp1 { self.obp1 } else { self.obp0 }, obj.color)
+            } else {
+                palette(self.bgp, bg)
+            };
+        self.framebuffer[self.ly as usize * SCREEN_WIDTH + x] = shade;
+
+        x + 1 == SCREEN_WIDTH
+    }
+}
+
+#[cfg(test)]
+mod fifo_tests {
+    use super::super::{
+        Mode, Ppu, Renderer, BGP_ADDRESS, LCDC_ADDRESS, LINES_PER_FRAME, OBP0_ADDRESS,
+        OBP1_ADDRESS, SCREEN_WIDTH, SCX_ADDRESS, SCY_ADDRESS, WX_ADDRESS, WY_ADDRESS,
+    };
+
+    /// Background, window and a handful of sprites over scrambled VRAM
+    fn scene(renderer: Renderer, scx: u8) -> Ppu {
+        let mut ppu = Ppu::default();
+        ppu.set_renderer(renderer);
+        ppu.write_register(LCDC_ADDRESS, 0x00);
+
+        let mut seed: u32 = 1;
+        for address in 0x8000..0x9C00 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            ppu.write_vram(address, (seed >> 16) as u8);
+        }
+        // x, y, flags: left edge, overlaps, flips, behind the background
+        let sprites = [
+            (0, 10, 0x00),
+            (3, 20, 0x20),
+            (40, 30, 0x10),
+            (44, 30, 0x80),
+            (44, 34, 0x40),
+            (100, 60, 0x00),
+            (163, 70, 0x00),
+        ];
+        for (i, &(x, y, flags)) in sprites.iter().enumerate() {
+            let address = 0xFE00 + i as u16 * 4;
+            ppu.write_oam(address, y + 16);
+            ppu.write_oam(address + 1, x);
+            ppu.write_oam(address + 2, i as u8 * 3);
+            ppu.write_oam(address + 3, flags);
+        }
+
+        ppu.write_register(BGP_ADDRESS, 0xE4);
+        ppu.write_register(OBP0_ADDRESS, 0xD2);
+        ppu.write_register(OBP1_ADDRESS, 0x1B);
+        ppu.write_register(SCX_ADDRESS, scx);
+        ppu.write_register(SCY_ADDRESS, 13);
+        ppu.write_register(WY_ADDRESS, 50);
+        ppu.write_register(WX_ADDRESS, 87);
+        ppu.write_register(LCDC_ADDRESS, 0xF3);
+        ppu
+    }
+
+    /// Runs a frame dot by dot, returning the length of each line's drawing
+    /// period
+    fn run_frame(ppu: &mut Ppu) -> Vec<u16> {
+        let mut lengths = vec![];
+        let mut start = 0;
+        for _ in 0..LINES_PER_FRAME as u32 * 456 {
+            let mode = ppu.mode;
+            ppu.tick(1);
+            match (mode, ppu.mode) {
+                (Mode::OamScan, Mode::Drawing) => start = ppu.dot,
+                (Mode::Drawing, Mode::HBlank) => lengths.push(ppu.dot - start),
+                _ => {}
+            }
+        }
+        lengths
+    }
+
+    /// Dots until the PPU reaches `x` pixels into drawing line 0
+    fn run_to_pixel(ppu: &mut Ppu, x: u8) {
+        while ppu.mode != Mode::Drawing || ppu.fifo.x < x {
+            ppu.tick(1);
+        }
+    }
+
+    fn run_to_hblank(ppu: &mut Ppu, ly: u8) {
+        while ppu.ly != ly || ppu.mode != Mode::HBlank {
+            ppu.tick(1);
+        }
+    }
+
+    #[test]
+    fn test_matches_scanline_renderer() {
+        for scx in [0, 3, 7] {
+            let mut scanline = scene(Renderer::Scanline, scx);
+            let mut fifo = scene(Renderer::Fifo, scx);
+
+            let scanline_lengths = run_frame(&mut scanline);
+            let fifo_lengths = run_frame(&mut fifo);
+
+            assert_eq!(fifo_lengths, scanline_lengths, "SCX {}", scx);
+            assert!(
+                fifo.framebuffer() == scanline.framebuffer(),
+                "SCX {}: frames differ",
+                scx
+            );
+        }
+    }
+
+    #[test]
+    fn test_mid_line_palette_change() {
+        let mut ppu = scene(Renderer::Fifo, 0);
+        ppu.write_register(LCDC_ADDRESS, 0x00);
+        // a solid color 3 background
+        for address in 0x8000..0x8010 {
+            ppu.write_vram(address, 0xFF);
+        }
+        for address in 0x9800..0x9C00 {
+            ppu.write_vram(address, 0);
+        }
+        ppu.write_register(LCDC_ADDRESS, 0x91);
+
+        run_to_pixel(&mut ppu, 80);
+        ppu.write_register(BGP_ADDRESS, 0x00);
+        run_to_hblank(&mut ppu, 0);
+
+        assert_eq!(ppu.framebuffer()[79], 3);
+        assert_eq!(ppu.framebuffer()[80], 0);
+    }
+
+    #[test]
+    fn test_mid_line_sprite_fetch() {
+        let mut ppu = scene(Renderer::Fifo, 0);
+        ppu.write_register(LCDC_ADDRESS, 0x00);
+        // a color 0 background, tile 1 is the solid bottom half of an 8x16
+        // sprite whose row 8 is on line 0
+        for address in 0x8000..0x8010 {
+            ppu.write_vram(address, 0);
+        }
+        for address in 0x8010..0x8020 {
+            ppu.write_vram(address, 0xFF);
+        }
+        for address in 0x9800..0x9C00 {
+            ppu.write_vram(address, 0);
+        }
+        for (i, x) in [8, 108].into_iter().enumerate() {
+            let address = 0xFE00 + i as u16 * 4;
+            ppu.write_oam(address, 8);
+            ppu.write_oam(address + 1, x);
+            ppu.write_oam(address + 2, 0);
+            ppu.write_oam(address + 3, 0);
+        }
+        for address in 0xFE08..0xFEA0 {
+            ppu.write_oam(address, 0);
+        }
+        ppu.write_register(OBP0_ADDRESS, 0xE4);
+        ppu.write_register(LCDC_ADDRESS, 0x97);
+
+        // the first sprite has picked its row, switching to 8x8 sprites
+        // only drops the second one and the high byte is read afterwards
+        while ppu
+            .fifo
+            .sprite_fetch
+            .is_none_or(|fetch| fetch.address.is_none())
+        {
+            ppu.tick(1);
+        }
+        ppu.write_register(LCDC_ADDRESS, 0x93);
+        ppu.write_vram(0x8011, 0x00);
+        run_to_hblank(&mut ppu, 0);
+
+        assert_eq!(ppu.framebuffer()[0], 1);
+        assert_eq!(ppu.framebuffer()[7], 1);
+        assert_eq!(ppu.framebuffer()[100], 0);
+    }
+
+    #[test]
+    fn test_mid_line_window_disable() {
+        let mut ppu = scene(Renderer::Fifo, 0);
+        ppu.write_register(LCDC_ADDRESS, 0x00);
+        // background tile 0 is color 0, the window's tile 1 color 3
+        for address in 0x8000..0x8010 {
+            ppu.write_vram(address, 0);
+        }
+        for address in 0x8010..0x8020 {
+            ppu.write_vram(address, 0xFF);
+        }
+        for address in 0x9800..0x9C00 {
+            ppu.write_vram(address, 0);
+        }
+        for address in 0x9C00..0xA000 {
+            ppu.write_vram(address, 1);
+        }
+        ppu.write_register(WY_ADDRESS, 0);
+        ppu.write_register(WX_ADDRESS, 7);
+        ppu.write_register(LCDC_ADDRESS, 0xF1);
+
+        // the window only shows from where the fetcher switched to it,
+        // turning it off after that doesn't bring the background back
+        run_to_pixel(&mut ppu, 16);
+        ppu.write_register(LCDC_ADDRESS, 0xD1);
+        run_to_hblank(&mut ppu, 1);
+
+        assert_eq!(ppu.framebuffer()[0], 3);
+        assert_eq!(ppu.framebuffer()[159], 3);
+        assert_eq!(ppu.framebuffer()[SCREEN_WIDTH], 0);
+    }
+}
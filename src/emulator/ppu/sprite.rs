@@ -0,0 +1,106 @@
+/// Most objects the PPU can draw on one line, the rest are dropped
+pub const SPRITES_PER_LINE: usize = 10;
+
+/// Object drawn behind background colors 1-3
+pub const BG_PRIORITY: u8 = 0x80;
+pub const Y_FLIP: u8 = 0x40;
+pub const X_FLIP: u8 = 0x20;
+/// OBP1 rather than OBP0
+pub const PALETTE: u8 = 0x10;
+
+/// Objects are stored offset so they can slide in from the top left
+const Y_OFFSET: i16 = 16;
+const X_OFFSET: i16 = 8;
+
+/// One 4-byte OAM entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: u8,
+}
+
+impl Sprite {
+    pub fn from_oam(entry: &[u8]) -> Self {
+        Self {
+            y: entry[0],
+            x: entry[1],
+            tile: entry[2],
+            flags: entry[3],
+        }
+    }
+
+    /// Row of the sprite on line `ly`, if it covers that line at all
+    pub fn row(&self, ly: u8, height: u8) -> Option<u8> {
+        let row = ly as i16 - (self.y as i16 - Y_OFFSET);
+        (0..height as i16).contains(&row).then_some(row as u8)
+    }
+
+    /// Column of the sprite at screen X `x`, if it covers that pixel
+    pub fn column(&self, x: u8) -> Option<u8> {
+        let column = x as i16 - (self.x as i16 - X_OFFSET);
+        (0..8).contains(&column).then_some(column as u8)
+    }
+}
+
+/// OAM search: the first `SPRITES_PER_LINE` objects on line `ly` in OAM
+/// order, then sorted into DMG drawing priority. Lower X wins, ties go to
+/// the earlier OAM entry.
+pub fn scan(oam: &[u8], ly: u8, height: u8) -> Vec<Sprite> {
+    let mut sprites: Vec<Sprite> = oam
+        .chunks_exact(4)
+        .map(Sprite::from_oam)
+        .filter(|sprite| sprite.row(ly, height).is_some())
+        .take(SPRITES_PER_LINE)
+        .collect();
+    // stable, so OAM order breaks ties
+    sprites.sort_by_key(|sprite| sprite.x);
+    sprites
+}
+
+#[cfg(test)]
+mod sprite_tests {
+    use super::{scan, Sprite};
+
+    fn oam(sprites: &[(u8, u8)]) -> Vec<u8> {
+        let mut oam = vec![0; 0xA0];
+        for (i, &(y, x)) in sprites.iter().enumerate() {
+            oam[i * 4] = y;
+            oam[i * 4 + 1] = x;
+            oam[i * 4 + 2] = i as u8;
+        }
+        oam
+    }
+
+    #[test]
+    fn test_scan_limit_ignores_x() {
+        // 12 sprites on line 0, the first two off screen horizontally still count
+        let mut sprites = vec![(16, 0); 2];
+        sprites.extend(vec![(16, 50); 10]);
+
+        let found = scan(&oam(&sprites), 0, 8);
+
+        assert_eq!(found.len(), 10);
+        assert_eq!(found.iter().filter(|sprite| sprite.x == 0).count(), 2);
+    }
+
+    #[test]
+    fn test_scan_sorts_by_x_then_oam_index() {
+        let found = scan(&oam(&[(16, 30), (16, 20), (16, 30)]), 0, 8);
+
+        let tiles: Vec<u8> = found.iter().map(|sprite| sprite.tile).collect();
+        assert_eq!(tiles, [1, 0, 2]);
+    }
+
+    #[test]
+    fn test_row_with_height() {
+        let sprite = Sprite::from_oam(&[16, 8, 0, 0]);
+
+        assert_eq!(sprite.row(7, 8), Some(7));
+        assert_eq!(sprite.row(8, 8), None);
+        assert_eq!(sprite.row(15, 16), Some(15));
+        // partly above the screen
+        assert_eq!(Sprite::from_oam(&[10, 8, 0, 0]).row(0, 8), Some(6));
+    }
+}
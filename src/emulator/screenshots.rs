@@ -0,0 +1,172 @@
+//! Runs test ROMs headlessly and compares the framebuffer with reference
+//! screenshots. The ROMs aren't distributed with the repo, these only run
+//! when `TEST_ROM_DIR` points at a directory holding them.
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use super::{EmulatorBuilder, Renderer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const TEST_ROM_DIR_VAR: &str = "TEST_ROM_DIR";
+
+/// Mealybug Tearoom tests with DMG references, all of them change PPU
+/// registers mid-line
+const MEALYBUG_TESTS: [&str; 24] = [
+    "m2_win_en_toggle",
+    "m3_bgp_change",
+    "m3_bgp_change_sprites",
+    "m3_lcdc_bg_en_change",
+    "m3_lcdc_bg_map_change",
+    "m3_lcdc_obj_en_change",
+    "m3_lcdc_obj_en_change_variant",
+    "m3_lcdc_obj_size_change",
+    "m3_lcdc_obj_size_change_scx",
+    "m3_lcdc_tile_sel_change",
+    "m3_lcdc_tile_sel_win_change",
+    "m3_lcdc_win_en_change_multiple",
+    "m3_lcdc_win_en_change_multiple_wx",
+    "m3_lcdc_win_map_change",
+    "m3_obp0_change",
+    "m3_scx_high_5_bits",
+    "m3_scx_low_3_bits",
+    "m3_scy_change",
+    "m3_window_timing",
+    "m3_window_timing_wx_0",
+    "m3_wx_4_change",
+    "m3_wx_4_change_sprites",
+    "m3_wx_5_change",
+    "m3_wx_6_change",
+];
+
+/// Reference screenshot converted to DMG shades, the references use the four
+/// greys 0xFF, 0xAA, 0x55 and 0x00
+fn load_screenshot(path: &Path) -> Vec<u8> {
+    let mut decoder = png::Decoder::new(File::open(path).unwrap());
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    assert_eq!(
+        (info.width as usize, info.height as usize),
+        (SCREEN_WIDTH, SCREEN_HEIGHT),
+        "{} isn't a screenshot",
+        path.display()
+    );
+
+    // the first channel is enough for grey
+    let channels = info.color_type.samples();
+    buf[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|pixel| 3 - pixel[0] / 0x55)
+        .collect()
+}
+
+/// Runs `rom` for `frames` frames and checks the final frame against
+/// `screenshot`
+fn check_rom(
+    rom: &Path,
+    screenshot: &Path,
+    frames: usize,
+    renderer: Renderer,
+) -> Result<(), String> {
+    let mut emulator = EmulatorBuilder::new()
+        .cart(rom.to_str().unwrap().to_string())
+        .map_err(|e| e.to_string())?
+        .renderer(renderer)
+        .build();
+    for _ in 0..frames {
+        emulator.run_frame().map_err(|e| e.to_string())?;
+    }
+
+    let expected = load_screenshot(screenshot);
+    let mismatches: Vec<usize> = (0..expected.len())
+        .filter(|&i| emulator.framebuffer()[i] != expected[i])
+        .collect();
+    match mismatches.first() {
+        None => Ok(()),
+        Some(&first) => Err(format!(
+            "{}: {} pixels differ, first at ({}, {}), got shade {} instead of {}",
+            rom.display(),
+            mismatches.len(),
+            first % SCREEN_WIDTH,
+            first / SCREEN_WIDTH,
+            emulator.framebuffer()[first],
+            expected[first]
+        )),
+    }
+}
+
+/// `name` under `TEST_ROM_DIR`. The tests using it are ignored by default,
+/// so running them without the ROMs is a mistake rather than a skip.
+pub(super) fn test_rom_dir(name: &str) -> PathBuf {
+    let dir = std::env::var(TEST_ROM_DIR_VAR)
+        .unwrap_or_else(|_| panic!("{} must be set to run ROM tests", TEST_ROM_DIR_VAR));
+    let dir = Path::new(&dir).join(name);
+    assert!(dir.is_dir(), "{} not found", dir.display());
+    dir
+}
+
+#[test]
+#[ignore = "needs dmg-acid2 under TEST_ROM_DIR"]
+fn test_dmg_acid2() {
+    let dir = test_rom_dir("dmg-acid2");
+
+    for renderer in [Renderer::Scanline, Renderer::Fifo] {
+        let result = check_rom(
+            &dir.join("dmg-acid2.gb"),
+            &dir.join("reference-dmg.png"),
+            60,
+            renderer,
+        );
+
+        if let Err(e) = result {
+            panic!("{:?}: {}", renderer, e);
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs mealybug-tearoom-tests under TEST_ROM_DIR"]
+fn test_mealybug_tearoom() {
+    let dir = test_rom_dir("mealybug-tearoom-tests");
+
+    let failures: Vec<String> = MEALYBUG_TESTS
+        .iter()
+        .filter_map(|name| {
+            check_rom(
+                &dir.join("build/ppu").join(format!("{}.gb", name)),
+                &dir.join("expected/DMG-blob").join(format!("{}.png", name)),
+                10,
+                Renderer::Fifo,
+            )
+            .err()
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_load_screenshot() {
+    let path = std::env::temp_dir().join(format!("rgbe-screenshot-{}.png", std::process::id()));
+    let greys = [0xFF, 0xAA, 0x55, 0x00];
+    let pixels: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+        .map(|i| greys[i % 4])
+        .collect();
+
+    let mut encoder = png::Encoder::new(
+        File::create(&path).unwrap(),
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&pixels).unwrap();
+    writer.finish().unwrap();
+
+    let shades = load_screenshot(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(shades[..5], [0, 1, 2, 3, 0]);
+}
@@ -0,0 +1,245 @@
+use super::interrupts::Interrupt;
+
+pub const DIV_ADDRESS: u16 = 0xFF04;
+pub const TIMA_ADDRESS: u16 = 0xFF05;
+pub const TMA_ADDRESS: u16 = 0xFF06;
+pub const TAC_ADDRESS: u16 = 0xFF07;
+
+const TAC_ENABLE: u8 = 0x04;
+/// TAC bits 3-7 aren't wired and read back as 1
+const TAC_UNUSED: u8 = 0xF8;
+/// T-cycles the divider advances per M-cycle
+const DIVIDER_STEP: u16 = 4;
+
+/// Where TIMA stands after overflowing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Reload {
+    #[default]
+    Idle,
+    /// TIMA reads 0 for one M-cycle before TMA is loaded, a TIMA write in
+    /// that cycle cancels the reload and the interrupt
+    Pending,
+    /// The M-cycle TMA is copied in, TIMA writes are lost and TMA writes go
+    /// through to TIMA as well
+    Reloading,
+}
+
+/// DIV/TIMA/TMA/TAC. DIV is the top byte of a 16-bit divider counting
+/// T-cycles, TIMA counts falling edges of the divider bit TAC selects.
+/// Since the edge detector sees the bit ANDed with the enable, writes that
+/// drop that signal count as an edge too.
+#[derive(Debug, Default)]
+pub struct Timer {
+    divider: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    reload: Reload,
+    /// Interrupts raised since the bus last collected them
+    requested: u8,
+}
+
+impl Timer {
+    /// Interrupts raised since the last call, as IF bits
+    pub fn take_interrupts(&mut self) -> u8 {
+        std::mem::take(&mut self.requested)
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            DIV_ADDRESS => (self.divider >> 8) as u8,
+            TIMA_ADDRESS => self.tima,
+            TMA_ADDRESS => self.tma,
+            TAC_ADDRESS => self.tac | TAC_UNUSED,
+            _ => panic!("Address {:#06x} is not a timer register", address),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let signal = self.signal();
+        match address {
+            DIV_ADDRESS => self.divider = 0,
+            TIMA_ADDRESS => match self.reload {
+                Reload::Idle => self.tima = value,
+                Reload::Pending => {
+                    self.tima = value;
+                    self.reload = Reload::Idle;
+                }
+                Reload::Reloading => {}
+            },
+            TMA_ADDRESS => {
+                self.tma = value;
+                if self.reload == Reload::Reloading {
+                    self.tima = value;
+                }
+            }
+            TAC_ADDRESS => self.tac = value & !TAC_UNUSED,
+            _ => panic!("Address {:#06x} is not a timer register", address),
+        }
+        if signal && !self.signal() {
+            self.increment();
+        }
+    }
+
+    /// Advances by one M-cycle
+    pub fn step(&mut self) {
+        match self.reload {
+            Reload::Pending => {
+                self.tima = self.tma;
+                self.requested |= Interrupt::Timer.mask();
+                self.reload = Reload::Reloading;
+            }
+            Reload::Reloading => self.reload = Reload::Idle,
+            Reload::Idle => {}
+        }
+
+        let signal = self.signal();
+        self.divider = self.divider.wrapping_add(DIVIDER_STEP);
+        if signal && !self.signal() {
+            self.increment();
+        }
+    }
+
+    /// Divider bit selected by TAC, ANDed with the enable
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0x03 {
+            0 => 9,
+            1 => 3,
+            2 => 5,
+            _ => 7,
+        };
+        self.tac & TAC_ENABLE != 0 && self.divider >> bit & 1 != 0
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        if overflow {
+            self.reload = Reload::Pending;
+        }
+    }
+}
+
+#[cfg(test)]
+mod timer_tests {
+    use super::{Timer, DIV_ADDRESS, TAC_ADDRESS, TIMA_ADDRESS, TMA_ADDRESS};
+
+    fn run(timer: &mut Timer, m_cycles: u32) {
+        for _ in 0..m_cycles {
+            timer.step();
+        }
+    }
+
+    #[test]
+    fn test_div_counts_t_cycles() {
+        let mut timer = Timer::default();
+        run(&mut timer, 64);
+        assert_eq!(timer.read(DIV_ADDRESS), 1);
+
+        timer.write(DIV_ADDRESS, 0x45);
+        assert_eq!(timer.read(DIV_ADDRESS), 0);
+    }
+
+    #[test]
+    fn test_tima_frequencies() {
+        // 1024, 16, 64 and 256 T-cycles per increment
+        for (tac, m_cycles) in [(0x04, 256), (0x05, 4), (0x06, 16), (0x07, 64)] {
+            let mut timer = Timer::default();
+            timer.write(TAC_ADDRESS, tac);
+
+            run(&mut timer, m_cycles - 1);
+            assert_eq!(timer.read(TIMA_ADDRESS), 0, "TAC {:#04x}", tac);
+            run(&mut timer, 1);
+            assert_eq!(timer.read(TIMA_ADDRESS), 1, "TAC {:#04x}", tac);
+        }
+    }
+
+    #[test]
+    fn test_disabled_timer_keeps_tima() {
+        let mut timer = Timer::default();
+        timer.write(TAC_ADDRESS, 0x01);
+        run(&mut timer, 100);
+
+        assert_eq!(timer.read(TIMA_ADDRESS), 0);
+        assert_eq!(timer.read(TAC_ADDRESS), 0xF9);
+    }
+
+    #[test]
+    fn test_overflow_reloads_a_cycle_late() {
+        let mut timer = Timer::default();
+        timer.write(TMA_ADDRESS, 0xAB);
+        timer.write(TIMA_ADDRESS, 0xFF);
+        timer.write(TAC_ADDRESS, 0x05);
+
+        run(&mut timer, 4);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0);
+        assert_eq!(timer.take_interrupts(), 0);
+
+        run(&mut timer, 1);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0xAB);
+        assert_eq!(timer.take_interrupts(), 0x04);
+    }
+
+    #[test]
+    fn test_tima_write_cancels_pending_reload() {
+        let mut timer = Timer::default();
+        timer.write(TMA_ADDRESS, 0xAB);
+        timer.write(TIMA_ADDRESS, 0xFF);
+        timer.write(TAC_ADDRESS, 0x05);
+        run(&mut timer, 4);
+
+        timer.write(TIMA_ADDRESS, 0x10);
+        run(&mut timer, 1);
+
+        assert_eq!(timer.read(TIMA_ADDRESS), 0x10);
+        assert_eq!(timer.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn test_writes_while_reloading() {
+        let mut timer = Timer::default();
+        timer.write(TMA_ADDRESS, 0xAB);
+        timer.write(TIMA_ADDRESS, 0xFF);
+        timer.write(TAC_ADDRESS, 0x05);
+        run(&mut timer, 5);
+
+        // TIMA writes are lost, TMA writes land in TIMA too
+        timer.write(TIMA_ADDRESS, 0x10);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0xAB);
+        timer.write(TMA_ADDRESS, 0x20);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0x20);
+
+        run(&mut timer, 1);
+        timer.write(TMA_ADDRESS, 0x30);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0x20);
+    }
+
+    #[test]
+    fn test_div_write_glitch() {
+        let mut timer = Timer::default();
+        timer.write(TAC_ADDRESS, 0x05);
+        // bit 3 set, not yet falling
+        run(&mut timer, 2);
+        assert_eq!(timer.read(TIMA_ADDRESS), 0);
+
+        timer.write(DIV_ADDRESS, 0);
+
+        assert_eq!(timer.read(TIMA_ADDRESS), 1);
+    }
+
+    #[test]
+    fn test_tac_write_glitch() {
+        let mut timer = Timer::default();
+        timer.write(TAC_ADDRESS, 0x05);
+        run(&mut timer, 2);
+
+        // disabling drops the signal
+        timer.write(TAC_ADDRESS, 0x01);
+        assert_eq!(timer.read(TIMA_ADDRESS), 1);
+
+        // so does switching to a bit that's low
+        timer.write(TAC_ADDRESS, 0x05);
+        timer.write(TAC_ADDRESS, 0x04);
+        assert_eq!(timer.read(TIMA_ADDRESS), 2);
+    }
+}
@@ -1,23 +1,72 @@
+mod bindings;
 mod context;
 mod emulator;
 
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bindings::Bindings;
 use context::{SDLContext, UpdateEvent};
-use emulator::{Emulator, EmulatorBuilder};
+use emulator::{Emulator, EmulatorBuilder, CLOCK_SPEED, CYCLES_PER_FRAME};
+
+/// Key and controller bindings, the defaults are used if it doesn't exist
+const BINDINGS_PATH: &str = "bindings.json";
 
 pub fn main() {
     let rom_path = std::env::var("TEST_ROM_DIR").unwrap();
-    let mut emulator: Emulator = EmulatorBuilder::new()
-        .cart(format!("{rom_path}/cpu_instrs/cpu_instrs.gb"))
-        .build();
+    let rom = format!("{rom_path}/cpu_instrs/cpu_instrs.gb");
+    let builder = match EmulatorBuilder::new().cart(rom) {
+        Ok(builder) => builder,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    if !builder.header().global_checksum_ok {
+        eprintln!(
+            "Global checksum mismatch for {}, continuing",
+            builder.header().title
+        );
+    }
+    let mut emulator: Emulator = builder.build();
 
-    let mut context = SDLContext::new();
+    let bindings = match Bindings::load(Path::new(BINDINGS_PATH)) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let mut context = SDLContext::new(bindings);
+    let frame_duration = Duration::from_nanos(CYCLES_PER_FRAME * 1_000_000_000 / CLOCK_SPEED);
 
     'running: loop {
-        match context.update() {
+        let frame_start = Instant::now();
+
+        match context.update(&mut emulator) {
             UpdateEvent::Stop => break 'running,
             _ => {}
         }
-        emulator.update();
-        context.render();
+        if !context.paused() {
+            if let Err(e) = emulator.run_frame() {
+                eprintln!("{}", e);
+                break 'running;
+            }
+            if let Err(e) = emulator.autosave() {
+                eprintln!("Couldn't save: {}", e);
+            }
+        }
+        context.render(emulator.framebuffer());
+
+        // ~59.7 frames per second, as fast as possible while fast-forwarding
+        if !context.fast_forward() {
+            std::thread::sleep(frame_duration.saturating_sub(frame_start.elapsed()));
+        }
+    }
+
+    if let Err(e) = emulator.save() {
+        eprintln!("Couldn't save: {}", e);
     }
 }